@@ -10,5 +10,11 @@ pub fn get_args() -> ArgMatches {
                 .help("Pretty print the ast")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("infer-params")
+                .long("infer-params")
+                .help("Infer the types of unannotated parameters from their uses")
+                .action(ArgAction::SetTrue),
+        )
         .get_matches()
 }
@@ -1,4 +1,4 @@
-use tree_sitter::TreeCursor;
+use tree_sitter::{Node, TreeCursor};
 
 pub fn visit_all_children(cursor: &mut TreeCursor, visit_cb: &mut dyn FnMut(&mut TreeCursor)) {
     visit_cb(cursor);
@@ -16,6 +16,45 @@ pub fn visit_all_children(cursor: &mut TreeCursor, visit_cb: &mut dyn FnMut(&mut
     }
 }
 
+/// Pre-order walk like `visit_all_children`, but the callback decides whether the
+/// children of the current node get visited. Returning `false` skips the subtree
+pub fn visit_children_pruned(
+    cursor: &mut TreeCursor,
+    visit_cb: &mut dyn FnMut(&mut TreeCursor) -> bool,
+) {
+    if !visit_cb(cursor) || !cursor.goto_first_child() {
+        return;
+    }
+    loop {
+        visit_children_pruned(cursor, visit_cb);
+        if !cursor.goto_next_sibling() {
+            cursor.goto_parent();
+            break;
+        }
+    }
+}
+
+/// Identifiers an `import` or `from ... import` statement binds
+/// `import a.b` binds `a` and `import a as b` binds `b`
+pub fn imported_ids<'t>(stmt: &Node<'t>) -> Vec<Node<'t>> {
+    stmt.children_by_field_name("name", &mut stmt.walk())
+        .filter_map(|name| match name.kind() {
+            "aliased_import" => name.child_by_field_name("alias"),
+            _ => name.named_child(0),
+        })
+        .collect()
+}
+
+/// Name in the imported module that an identifier from `imported_ids` refers to
+/// `from m import a as b` gives `a` for `b`
+pub fn imported_name<'t>(id: &Node<'t>) -> Option<Node<'t>> {
+    let parent = id.parent()?;
+    match parent.kind() {
+        "aliased_import" => parent.child_by_field_name("name"),
+        _ => Some(parent),
+    }
+}
+
 pub fn parse(src: &str) -> Option<tree_sitter::Tree> {
     let mut parser = tree_sitter::Parser::new();
     parser
@@ -24,3 +63,15 @@ pub fn parse(src: &str) -> Option<tree_sitter::Tree> {
 
     parser.parse(src, None)
 }
+
+/// Parse only `range` of `src`, node positions stay relative to the whole of `src`
+/// Used for string annotations like `x: "int"`
+pub fn parse_range(src: &str, range: tree_sitter::Range) -> Option<tree_sitter::Tree> {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_python::LANGUAGE.into())
+        .expect("Error loading Python grammar");
+    parser.set_included_ranges(&[range]).ok()?;
+
+    parser.parse(src, None)
+}
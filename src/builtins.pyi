@@ -0,0 +1,389 @@
+# Signatures for python builtins
+# This is checked at startup and its module scope becomes the builtins scope.
+# Only the signatures matter, bodies are never used.
+
+def print(*values: object, sep: str = ..., end: str = ..., file: object = ..., flush: bool = ...) -> None: ...
+def input(prompt: object = ..., /) -> str: ...
+def len(obj: object, /) -> int: ...
+def repr(obj: object, /) -> str: ...
+def ascii(obj: object, /) -> str: ...
+def id(obj: object, /) -> int: ...
+def hash(obj: object, /) -> int: ...
+# the result has the type of the argument, which can't be described yet
+def abs(x: float, /) -> object: ...
+def chr(i: int, /) -> str: ...
+def ord(c: str, /) -> int: ...
+def bin(x: int, /) -> str: ...
+def oct(x: int, /) -> str: ...
+def hex(x: int, /) -> str: ...
+# an int without `ndigits` and a float with it, which can't be described yet
+def round(number: float, ndigits: int | None = ...) -> object: ...
+def callable(obj: object, /) -> bool: ...
+def isinstance(obj: object, class_or_tuple: object, /) -> bool: ...
+def sorted(iterable: object, /, *, key: object = ..., reverse: bool = ...) -> list: ...
+def sum(iterable: object, /, start: object = ...) -> object: ...
+def min(*args: object, key: object = ..., default: object = ...) -> object: ...
+def max(*args: object, key: object = ..., default: object = ...) -> object: ...
+def enumerate(iterable: object, start: int = ...) -> object: ...
+def zip(*iterables: object, strict: bool = ...) -> object: ...
+def open(file: object, mode: str = ..., buffering: int = ..., encoding: str | None = ..., errors: str | None = ..., newline: str | None = ...) -> object: ...
+def type(obj: object, /) -> object: ...
+def super(*args: object) -> object: ...
+def all(iterable: object, /) -> bool: ...
+def any(iterable: object, /) -> bool: ...
+def iter(obj: object, sentinel: object = ..., /) -> object: ...
+def next(iterator: object, default: object = ..., /) -> object: ...
+def aiter(async_iterable: object, /) -> object: ...
+def anext(aiterator: object, default: object = ..., /) -> object: ...
+def map(func: object, *iterables: object) -> object: ...
+def filter(function: object, iterable: object, /) -> object: ...
+def reversed(sequence: object, /) -> object: ...
+def divmod(x: object, y: object, /) -> object: ...
+def pow(base: object, exp: object, mod: object = ...) -> object: ...
+def format(value: object, format_spec: str = ..., /) -> str: ...
+def getattr(obj: object, name: str, default: object = ..., /) -> object: ...
+def hasattr(obj: object, name: str, /) -> bool: ...
+def setattr(obj: object, name: str, value: object, /) -> None: ...
+def delattr(obj: object, name: str, /) -> None: ...
+def issubclass(cls: object, class_or_tuple: object, /) -> bool: ...
+def dir(obj: object = ..., /) -> list: ...
+def vars(obj: object = ..., /) -> dict: ...
+def globals() -> dict: ...
+def locals() -> dict: ...
+def eval(source: object, globals: object = ..., locals: object = ..., /) -> object: ...
+def exec(source: object, globals: object = ..., locals: object = ..., /) -> None: ...
+def compile(source: object, filename: object, mode: str, flags: int = ..., dont_inherit: bool = ..., optimize: int = ...) -> object: ...
+def breakpoint(*args: object, **kws: object) -> None: ...
+def help(request: object = ...) -> None: ...
+def exit(code: object = ...) -> None: ...
+def quit(code: object = ...) -> None: ...
+def copyright() -> None: ...
+def credits() -> None: ...
+def license() -> None: ...
+
+__name__: str
+__file__: str
+__doc__: str | None
+__package__: str | None
+__spec__: object
+__loader__: object
+__builtins__: object
+__debug__: bool
+NotImplemented: object
+Ellipsis: object
+
+# classes
+# methods every object has are left untyped so subclasses can override them freely
+class object:
+    __class__: object
+    __dict__: dict
+    __doc__: str | None
+    __module__: str
+    __eq__: object
+    __ne__: object
+    __hash__: object
+    __repr__: object
+    __str__: object
+    __format__: object
+    __sizeof__: object
+    __reduce__: object
+    __reduce_ex__: object
+    __dir__: object
+    __getattribute__: object
+    __setattr__: object
+    __delattr__: object
+    __init_subclass__: object
+    __subclasshook__: object
+    def __init__(self) -> None: ...
+    def __new__(cls, *args: object, **kwargs: object) -> object: ...
+
+class BaseException:
+    args: object
+    __traceback__: object
+    __cause__: BaseException | None
+    __context__: BaseException | None
+    __suppress_context__: bool
+    __notes__: list
+    def __init__(self, *args: object) -> None: ...
+    def with_traceback(self, tb: object, /) -> object: ...
+    def add_note(self, note: str, /) -> None: ...
+
+class BaseExceptionGroup(BaseException):
+    message: str
+    exceptions: object
+class GeneratorExit(BaseException): ...
+class KeyboardInterrupt(BaseException): ...
+class SystemExit(BaseException):
+    code: object
+class Exception(BaseException): ...
+class ExceptionGroup(BaseExceptionGroup, Exception): ...
+class ArithmeticError(Exception): ...
+class FloatingPointError(ArithmeticError): ...
+class OverflowError(ArithmeticError): ...
+class ZeroDivisionError(ArithmeticError): ...
+class AssertionError(Exception): ...
+class AttributeError(Exception): ...
+class BufferError(Exception): ...
+class EOFError(Exception): ...
+class ImportError(Exception):
+    name: str | None
+    path: str | None
+    msg: str
+class ModuleNotFoundError(ImportError): ...
+class LookupError(Exception): ...
+class IndexError(LookupError): ...
+class KeyError(LookupError): ...
+class MemoryError(Exception): ...
+class NameError(Exception): ...
+class UnboundLocalError(NameError): ...
+class OSError(Exception):
+    errno: int | None
+    strerror: str | None
+    filename: object
+    filename2: object
+EnvironmentError = OSError
+IOError = OSError
+class BlockingIOError(OSError): ...
+class ChildProcessError(OSError): ...
+class ConnectionError(OSError): ...
+class BrokenPipeError(ConnectionError): ...
+class ConnectionAbortedError(ConnectionError): ...
+class ConnectionRefusedError(ConnectionError): ...
+class ConnectionResetError(ConnectionError): ...
+class FileExistsError(OSError): ...
+class FileNotFoundError(OSError): ...
+class InterruptedError(OSError): ...
+class IsADirectoryError(OSError): ...
+class NotADirectoryError(OSError): ...
+class PermissionError(OSError): ...
+class ProcessLookupError(OSError): ...
+class TimeoutError(OSError): ...
+class ReferenceError(Exception): ...
+class RuntimeError(Exception): ...
+class NotImplementedError(RuntimeError): ...
+class RecursionError(RuntimeError): ...
+class PythonFinalizationError(RuntimeError): ...
+class StopAsyncIteration(Exception): ...
+class StopIteration(Exception):
+    value: object
+class SyntaxError(Exception):
+    msg: str
+    filename: str | None
+    lineno: int | None
+    offset: int | None
+    text: str | None
+class IndentationError(SyntaxError): ...
+class TabError(IndentationError): ...
+class SystemError(Exception): ...
+class TypeError(Exception): ...
+class ValueError(Exception): ...
+class UnicodeError(ValueError):
+    encoding: str
+    reason: str
+    object: object
+    start: int
+    end: int
+class UnicodeDecodeError(UnicodeError): ...
+class UnicodeEncodeError(UnicodeError): ...
+class UnicodeTranslateError(UnicodeError): ...
+class Warning(Exception): ...
+class BytesWarning(Warning): ...
+class DeprecationWarning(Warning): ...
+class EncodingWarning(Warning): ...
+class FutureWarning(Warning): ...
+class ImportWarning(Warning): ...
+class PendingDeprecationWarning(Warning): ...
+class ResourceWarning(Warning): ...
+class RuntimeWarning(Warning): ...
+class SyntaxWarning(Warning): ...
+class UnicodeWarning(Warning): ...
+class UserWarning(Warning): ...
+
+# classes mostly used as decorators or wrappers
+class property:
+    def __init__(self, fget: object = ..., fset: object = ..., fdel: object = ..., doc: str | None = ...) -> None: ...
+
+class classmethod:
+    def __init__(self, f: object, /) -> None: ...
+
+class staticmethod:
+    def __init__(self, f: object, /) -> None: ...
+
+class slice:
+    def __init__(self, *args: object) -> None: ...
+
+class memoryview:
+    def __init__(self, obj: object) -> None: ...
+
+# primitive and container types
+# element types of containers can't be described here, the checker works them out
+class int:
+    def __init__(self, x: object = ..., /, base: int = ...) -> None: ...
+    def __add__(self, other: int, /) -> int: ...
+    def __sub__(self, other: int, /) -> int: ...
+    def __mul__(self, other: int, /) -> int: ...
+    def __floordiv__(self, other: int, /) -> int: ...
+    def __mod__(self, other: int, /) -> int: ...
+    def __pow__(self, other: int, /) -> int: ...
+    def __and__(self, other: int, /) -> int: ...
+    def __or__(self, other: int, /) -> int: ...
+    def __xor__(self, other: int, /) -> int: ...
+    def __lshift__(self, other: int, /) -> int: ...
+    def __rshift__(self, other: int, /) -> int: ...
+    def __truediv__(self, other: int, /) -> float: ...
+    def __lt__(self, other: int, /) -> bool: ...
+    def __le__(self, other: int, /) -> bool: ...
+    def __gt__(self, other: int, /) -> bool: ...
+    def __ge__(self, other: int, /) -> bool: ...
+    def __neg__(self) -> int: ...
+    def __pos__(self) -> int: ...
+    def __invert__(self) -> int: ...
+
+class bool(int):
+    def __init__(self, o: object = ..., /) -> None: ...
+
+class float:
+    def __init__(self, x: object = ..., /) -> None: ...
+    def __add__(self, other: float, /) -> float: ...
+    def __sub__(self, other: float, /) -> float: ...
+    def __mul__(self, other: float, /) -> float: ...
+    def __truediv__(self, other: float, /) -> float: ...
+    def __floordiv__(self, other: float, /) -> float: ...
+    def __mod__(self, other: float, /) -> float: ...
+    def __pow__(self, other: float, /) -> float: ...
+    def __radd__(self, other: float, /) -> float: ...
+    def __rsub__(self, other: float, /) -> float: ...
+    def __rmul__(self, other: float, /) -> float: ...
+    def __rtruediv__(self, other: float, /) -> float: ...
+    def __rfloordiv__(self, other: float, /) -> float: ...
+    def __rmod__(self, other: float, /) -> float: ...
+    def __rpow__(self, other: float, /) -> float: ...
+    def __lt__(self, other: float, /) -> bool: ...
+    def __le__(self, other: float, /) -> bool: ...
+    def __gt__(self, other: float, /) -> bool: ...
+    def __ge__(self, other: float, /) -> bool: ...
+    def __neg__(self) -> float: ...
+    def __pos__(self) -> float: ...
+
+class complex:
+    def __init__(self, real: object = ..., imag: object = ...) -> None: ...
+    def __neg__(self) -> complex: ...
+    def __pos__(self) -> complex: ...
+    def __add__(self, other: complex, /) -> complex: ...
+    def __sub__(self, other: complex, /) -> complex: ...
+    def __mul__(self, other: complex, /) -> complex: ...
+    def __truediv__(self, other: complex, /) -> complex: ...
+    def __pow__(self, other: complex, /) -> complex: ...
+    def __radd__(self, other: complex, /) -> complex: ...
+    def __rsub__(self, other: complex, /) -> complex: ...
+    def __rmul__(self, other: complex, /) -> complex: ...
+    def __rtruediv__(self, other: complex, /) -> complex: ...
+    def __rpow__(self, other: complex, /) -> complex: ...
+
+class str:
+    def __init__(self, object: object = ..., encoding: str = ..., errors: str = ...) -> None: ...
+    def __add__(self, other: str, /) -> str: ...
+    def __mul__(self, n: int, /) -> str: ...
+    def __rmul__(self, n: int, /) -> str: ...
+    def __mod__(self, value: object, /) -> str: ...
+    def __contains__(self, key: str, /) -> bool: ...
+    def __iter__(self) -> object: ...
+    def __lt__(self, other: str, /) -> bool: ...
+    def __le__(self, other: str, /) -> bool: ...
+    def __gt__(self, other: str, /) -> bool: ...
+    def __ge__(self, other: str, /) -> bool: ...
+
+class bytes:
+    def __init__(self, source: object = ..., encoding: str = ..., errors: str = ...) -> None: ...
+    def __add__(self, other: bytes, /) -> bytes: ...
+    def __mul__(self, n: int, /) -> bytes: ...
+    def __rmul__(self, n: int, /) -> bytes: ...
+    def __mod__(self, value: object, /) -> bytes: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+    def __lt__(self, other: bytes, /) -> bool: ...
+    def __le__(self, other: bytes, /) -> bool: ...
+    def __gt__(self, other: bytes, /) -> bool: ...
+    def __ge__(self, other: bytes, /) -> bool: ...
+
+class list:
+    def __init__(self, iterable: object = ..., /) -> None: ...
+    def __add__(self, other: list, /) -> list: ...
+    def __iadd__(self, other: object, /) -> list: ...
+    def __mul__(self, n: int, /) -> list: ...
+    def __rmul__(self, n: int, /) -> list: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+    def __lt__(self, other: list, /) -> bool: ...
+    def __le__(self, other: list, /) -> bool: ...
+    def __gt__(self, other: list, /) -> bool: ...
+    def __ge__(self, other: list, /) -> bool: ...
+
+class tuple:
+    def __init__(self, iterable: object = ..., /) -> None: ...
+    def __add__(self, other: object, /) -> object: ...
+    def __mul__(self, n: int, /) -> object: ...
+    def __rmul__(self, n: int, /) -> object: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+    def __lt__(self, other: object, /) -> bool: ...
+    def __le__(self, other: object, /) -> bool: ...
+    def __gt__(self, other: object, /) -> bool: ...
+    def __ge__(self, other: object, /) -> bool: ...
+
+class set:
+    def __init__(self, iterable: object = ..., /) -> None: ...
+    def __or__(self, other: set, /) -> set: ...
+    def __and__(self, other: set, /) -> set: ...
+    def __sub__(self, other: set, /) -> set: ...
+    def __xor__(self, other: set, /) -> set: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+    def __lt__(self, other: set, /) -> bool: ...
+    def __le__(self, other: set, /) -> bool: ...
+    def __gt__(self, other: set, /) -> bool: ...
+    def __ge__(self, other: set, /) -> bool: ...
+
+class dict:
+    def __init__(self, mapping: object = ..., /, **kwargs: object) -> None: ...
+    def __or__(self, other: dict, /) -> dict: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+    def keys(self) -> list: ...
+    def values(self) -> list: ...
+    def items(self) -> list: ...
+
+class frozenset:
+    def __init__(self, iterable: object = ..., /) -> None: ...
+    def __or__(self, other: frozenset, /) -> frozenset: ...
+    def __and__(self, other: frozenset, /) -> frozenset: ...
+    def __sub__(self, other: frozenset, /) -> frozenset: ...
+    def __xor__(self, other: frozenset, /) -> frozenset: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+
+class bytearray:
+    def __init__(self, source: object = ..., encoding: str = ..., errors: str = ...) -> None: ...
+    def __add__(self, other: object, /) -> bytearray: ...
+    def __mul__(self, n: int, /) -> bytearray: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> object: ...
+
+class range:
+    def __init__(self, start: int, stop: int = ..., step: int = ..., /) -> None: ...
+    def __contains__(self, key: object, /) -> bool: ...
+    def __iter__(self) -> range_iterator: ...
+
+class range_iterator:
+    def __iter__(self) -> range_iterator: ...
+    def __next__(self) -> int: ...
+
+# from the enum module, `from enum import Enum` binds these rather than `Any`
+# names assigned in the body of a subclass are its members
+class Enum:
+    name: str
+    value: object
+    def __init__(self, value: object, /) -> None: ...
+
+class IntEnum(int, Enum): ...
+class StrEnum(str, Enum): ...
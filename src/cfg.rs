@@ -0,0 +1,426 @@
+use tree_sitter::{Node, Point};
+
+/// Control flow graph for a list of statements
+/// Compound statements like `if` and `while` are split into blocks joined by edges,
+/// the first block is where the statements start
+pub struct Cfg<'t> {
+    pub blocks: Vec<Block<'t>>,
+    /// Reached by running off the end of the statements
+    pub exit: usize,
+}
+
+/// Statements that always run one after the other
+pub struct Block<'t> {
+    /// Where the block starts in the source
+    pub at: Point,
+    pub steps: Vec<Step<'t>>,
+    pub succs: Vec<Edge<'t>>,
+}
+
+/// Something to check in a block
+#[derive(Clone, Copy)]
+pub enum Step<'t> {
+    /// A simple statement or an expression, eg. the condition of an `if`
+    Node(Node<'t>),
+    /// The iterable of a `for` loop is evaluated once before the loop starts
+    ForIter(Node<'t>),
+    /// The target of a `for` loop is assigned the next element of the iterable
+    ForTarget(Node<'t>),
+    /// A context manager of a `with`, its `as` target is assigned what entering it gives
+    WithItem(Node<'t>),
+    /// The exceptions an `except` clause catches, its `as` target is assigned the one caught
+    Except(Node<'t>),
+    /// A `case` of a `match`, the names its pattern captures are assigned
+    Case(Node<'t>),
+}
+
+pub struct Edge<'t> {
+    pub to: usize,
+    /// A condition known to be true or false when this edge is taken
+    pub condition: Option<(Node<'t>, bool)>,
+}
+
+/// Where `break` and `continue` go in the innermost loop
+struct LoopTargets {
+    head: usize,
+    after: usize,
+}
+
+struct Builder<'t> {
+    blocks: Vec<Block<'t>>,
+    loops: Vec<LoopTargets>,
+    /// Reached by `return` and `raise`, and `break` or `continue` outside of a loop
+    leave: usize,
+}
+
+impl<'t> Cfg<'t> {
+    pub fn build(stmts: &[Node<'t>]) -> Self {
+        let start = stmts
+            .first()
+            .map_or(Point::new(0, 0), |s| s.start_position());
+        let end = stmts.last().map_or(start, |s| s.end_position());
+        let mut builder = Builder {
+            blocks: Vec::new(),
+            loops: Vec::new(),
+            leave: 0,
+        };
+        let entry = builder.new_block(start);
+        builder.leave = builder.new_block(end);
+        let last = builder.stmts(Some(entry), stmts.iter().copied());
+        let exit = builder.new_block(end);
+        builder.edge(last, exit, None);
+        Cfg {
+            blocks: builder.blocks,
+            exit,
+        }
+    }
+
+    /// Edges into block `idx`, as the block they leave and their index in its edges
+    pub fn preds(&self, idx: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.blocks.iter().enumerate().flat_map(move |(from, b)| {
+            b.succs
+                .iter()
+                .enumerate()
+                .filter(move |(_, e)| e.to == idx)
+                .map(move |(edge_idx, _)| (from, edge_idx))
+        })
+    }
+}
+
+impl<'t> Builder<'t> {
+    fn new_block(&mut self, at: Point) -> usize {
+        self.blocks.push(Block {
+            at,
+            steps: Vec::new(),
+            succs: Vec::new(),
+        });
+        self.blocks.len() - 1
+    }
+
+    /// Add an edge, nothing happens when `from` can't be reached
+    fn edge(&mut self, from: Option<usize>, to: usize, condition: Option<(Node<'t>, bool)>) {
+        if let Some(from) = from {
+            self.blocks[from].succs.push(Edge { to, condition });
+        }
+    }
+
+    /// Block continuing after a compound statement, `None` when nothing reaches it
+    fn reachable(&self, idx: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .any(|b| b.succs.iter().any(|e| e.to == idx))
+            .then_some(idx)
+    }
+
+    fn push(&mut self, block: usize, step: Step<'t>) {
+        self.blocks[block].steps.push(step);
+    }
+
+    /// Add statements starting in `cur`, returns the block the statements end in
+    /// Statements that can't be reached are left out
+    fn stmts(
+        &mut self,
+        mut cur: Option<usize>,
+        stmts: impl Iterator<Item = Node<'t>>,
+    ) -> Option<usize> {
+        for stmt in stmts {
+            cur = Some(self.stmt(cur?, &stmt)?);
+        }
+        cur
+    }
+
+    fn block_stmts(&mut self, cur: Option<usize>, block: Option<Node<'t>>) -> Option<usize> {
+        match block {
+            Some(block) => self.stmts(cur, block.named_children(&mut block.walk())),
+            None => cur,
+        }
+    }
+
+    fn stmt(&mut self, cur: usize, stmt: &Node<'t>) -> Option<usize> {
+        match stmt.kind() {
+            "comment" => Some(cur),
+            "if_statement" => self.if_stmt(cur, stmt),
+            "while_statement" => self.while_stmt(cur, stmt),
+            "for_statement" => self.for_stmt(cur, stmt),
+            "try_statement" => self.try_stmt(cur, stmt),
+            "match_statement" => self.match_stmt(cur, stmt),
+            "with_statement" => {
+                for clause in stmt.named_children(&mut stmt.walk()) {
+                    if clause.kind() != "with_clause" {
+                        continue;
+                    }
+                    for item in clause.named_children(&mut clause.walk()) {
+                        if item.kind() == "with_item" {
+                            self.push(cur, Step::WithItem(item));
+                        }
+                    }
+                }
+                self.block_stmts(Some(cur), stmt.child_by_field_name("body"))
+            }
+            "return_statement" | "raise_statement" => {
+                self.push(cur, Step::Node(*stmt));
+                self.edge(Some(cur), self.leave, None);
+                None
+            }
+            "break_statement" | "continue_statement" => {
+                let to = match self.loops.last() {
+                    Some(l) if stmt.kind() == "break_statement" => l.after,
+                    Some(l) => l.head,
+                    None => self.leave,
+                };
+                self.edge(Some(cur), to, None);
+                None
+            }
+            _ => {
+                self.push(cur, Step::Node(*stmt));
+                Some(cur)
+            }
+        }
+    }
+
+    fn if_stmt(&mut self, mut cur: usize, stmt: &Node<'t>) -> Option<usize> {
+        let mut cond = stmt.child_by_field_name("condition")?;
+        self.push(cur, Step::Node(cond));
+        let mut ends = Vec::new();
+        let consequence = self.new_block(stmt.start_position());
+        self.edge(Some(cur), consequence, Some((cond, true)));
+        ends.push(self.block_stmts(Some(consequence), stmt.child_by_field_name("consequence")));
+
+        let mut has_else = false;
+        for alt in stmt.children_by_field_name("alternative", &mut stmt.walk()) {
+            let alt_block = self.new_block(alt.start_position());
+            self.edge(Some(cur), alt_block, Some((cond, false)));
+            match alt.kind() {
+                "elif_clause" => {
+                    cond = alt.child_by_field_name("condition")?;
+                    self.push(alt_block, Step::Node(cond));
+                    let consequence = self.new_block(alt.start_position());
+                    self.edge(Some(alt_block), consequence, Some((cond, true)));
+                    let body = alt.child_by_field_name("consequence");
+                    ends.push(self.block_stmts(Some(consequence), body));
+                    cur = alt_block;
+                }
+                _ => {
+                    has_else = true;
+                    ends.push(self.block_stmts(Some(alt_block), alt.child_by_field_name("body")));
+                }
+            }
+        }
+
+        let after = self.new_block(stmt.end_position());
+        if !has_else {
+            self.edge(Some(cur), after, Some((cond, false)));
+        }
+        for end in ends {
+            self.edge(end, after, None);
+        }
+        self.reachable(after)
+    }
+
+    fn while_stmt(&mut self, cur: usize, stmt: &Node<'t>) -> Option<usize> {
+        let cond = stmt.child_by_field_name("condition")?;
+        let head = self.new_block(stmt.start_position());
+        self.edge(Some(cur), head, None);
+        self.push(head, Step::Node(cond));
+        let body = self.new_block(stmt.start_position());
+        self.edge(Some(head), body, Some((cond, true)));
+        let after = self.new_block(stmt.end_position());
+
+        self.loops.push(LoopTargets { head, after });
+        let end = self.block_stmts(Some(body), stmt.child_by_field_name("body"));
+        self.loops.pop();
+        self.edge(end, head, None);
+
+        // `while True:` only ends through a `break`
+        if cond.kind() != "true" {
+            self.loop_else(head, Some((cond, false)), stmt, after);
+        }
+        self.reachable(after)
+    }
+
+    fn for_stmt(&mut self, cur: usize, stmt: &Node<'t>) -> Option<usize> {
+        self.push(cur, Step::ForIter(*stmt));
+        let head = self.new_block(stmt.start_position());
+        self.edge(Some(cur), head, None);
+        let body = self.new_block(stmt.start_position());
+        self.edge(Some(head), body, None);
+        self.push(body, Step::ForTarget(*stmt));
+        let after = self.new_block(stmt.end_position());
+
+        self.loops.push(LoopTargets { head, after });
+        let end = self.block_stmts(Some(body), stmt.child_by_field_name("body"));
+        self.loops.pop();
+        self.edge(end, head, None);
+
+        self.loop_else(head, None, stmt, after);
+        self.reachable(after)
+    }
+
+    /// The `else` of a loop runs when the loop ends without a `break`
+    fn loop_else(
+        &mut self,
+        head: usize,
+        condition: Option<(Node<'t>, bool)>,
+        stmt: &Node<'t>,
+        after: usize,
+    ) {
+        match stmt.child_by_field_name("alternative") {
+            Some(alt) => {
+                let else_block = self.new_block(alt.start_position());
+                self.edge(Some(head), else_block, condition);
+                let end = self.block_stmts(Some(else_block), alt.child_by_field_name("body"));
+                self.edge(end, after, None);
+            }
+            None => self.edge(Some(head), after, condition),
+        }
+    }
+
+    /// Cases are tried in order, a case that matches anything ends the `match`
+    fn match_stmt(&mut self, cur: usize, stmt: &Node<'t>) -> Option<usize> {
+        for subject in stmt.children_by_field_name("subject", &mut stmt.walk()) {
+            self.push(cur, Step::Node(subject));
+        }
+        let mut test = cur;
+        let mut ends = Vec::new();
+        let mut exhaustive = false;
+        let body = stmt.child_by_field_name("body");
+        let clauses = body
+            .iter()
+            .flat_map(|b| b.named_children(&mut b.walk()).collect::<Vec<_>>())
+            .filter(|c| c.kind() == "case_clause");
+        for clause in clauses {
+            let case = self.new_block(clause.start_position());
+            self.edge(Some(test), case, None);
+            self.push(case, Step::Case(clause));
+            let consequence = clause.child_by_field_name("consequence");
+            match clause
+                .child_by_field_name("guard")
+                .and_then(|g| g.named_child(0))
+            {
+                Some(guard) => {
+                    self.push(case, Step::Node(guard));
+                    let body = self.new_block(clause.start_position());
+                    self.edge(Some(case), body, Some((guard, true)));
+                    ends.push(self.block_stmts(Some(body), consequence));
+                    let next = self.new_block(clause.end_position());
+                    self.edge(Some(test), next, None);
+                    self.edge(Some(case), next, Some((guard, false)));
+                    test = next;
+                }
+                None => {
+                    ends.push(self.block_stmts(Some(case), consequence));
+                    if is_irrefutable(&clause) {
+                        exhaustive = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let after = self.new_block(stmt.end_position());
+        if !exhaustive {
+            self.edge(Some(test), after, None);
+        }
+        for end in ends {
+            self.edge(end, after, None);
+        }
+        self.reachable(after)
+    }
+
+    /// Handlers can start from anywhere in the `try` body,
+    /// they are reached from before the body and from the end of each of its statements
+    /// `finally` is added twice, once continuing after the `try` and once for leaving it
+    /// through `return` or an exception
+    fn try_stmt(&mut self, cur: usize, stmt: &Node<'t>) -> Option<usize> {
+        let body = self.new_block(stmt.start_position());
+        self.edge(Some(cur), body, None);
+        let body_end = self.try_body(body, stmt.child_by_field_name("body"));
+        // an exception can be raised after any statement of the body
+        let raising = body..self.blocks.len();
+
+        let mut ends = Vec::new();
+        let mut else_end = body_end;
+        let mut finally = None;
+        for clause in stmt.named_children(&mut stmt.walk()) {
+            let block = clause
+                .named_children(&mut clause.walk())
+                .find(|n| n.kind() == "block");
+            match clause.kind() {
+                "except_clause" | "except_group_clause" => {
+                    let handler = self.new_block(clause.start_position());
+                    self.edge(Some(cur), handler, None);
+                    for from in raising.clone() {
+                        self.edge(Some(from), handler, None);
+                    }
+                    self.push(handler, Step::Except(clause));
+                    ends.push(self.block_stmts(Some(handler), block));
+                }
+                "else_clause" => {
+                    let else_block = self.new_block(clause.start_position());
+                    self.edge(body_end, else_block, None);
+                    let body = clause.child_by_field_name("body");
+                    else_end = self.block_stmts(self.reachable(else_block), body);
+                }
+                "finally_clause" => finally = Some((clause, block)),
+                _ => {}
+            }
+        }
+        ends.push(else_end);
+
+        let after = self.new_block(stmt.end_position());
+        match finally {
+            Some((clause, block)) => {
+                let finally_block = self.new_block(clause.start_position());
+                for end in ends {
+                    self.edge(end, finally_block, None);
+                }
+                let end = self.block_stmts(self.reachable(finally_block), block);
+                self.edge(end, after, None);
+
+                let leaving = self.new_block(clause.start_position());
+                self.edge(Some(cur), leaving, None);
+                for from in body..finally_block {
+                    self.edge(Some(from), leaving, None);
+                }
+                let end = self.block_stmts(Some(leaving), block);
+                self.edge(end, self.leave, None);
+            }
+            None => {
+                for end in ends {
+                    self.edge(end, after, None);
+                }
+            }
+        }
+        self.reachable(after)
+    }
+
+    /// Statements of a `try` body, each ending its own block
+    /// so the handlers can be reached from the vars after any of them
+    fn try_body(&mut self, body: usize, block: Option<Node<'t>>) -> Option<usize> {
+        let mut cur = Some(body);
+        let Some(block) = block else {
+            return cur;
+        };
+        for stmt in block.named_children(&mut block.walk()) {
+            if stmt.kind() == "comment" {
+                continue;
+            }
+            let end = self.stmt(cur?, &stmt);
+            let next = self.new_block(stmt.end_position());
+            self.edge(end, next, None);
+            cur = self.reachable(next);
+        }
+        cur
+    }
+}
+
+/// Whether the pattern of a `case` matches anything, like `case _:` or `case x:`
+fn is_irrefutable(clause: &Node) -> bool {
+    let Some(pattern) = clause.named_child(0).filter(|p| p.kind() == "case_pattern") else {
+        return false;
+    };
+    match pattern.named_child(0) {
+        None => true,
+        Some(capture) => capture.kind() == "dotted_name" && capture.named_child_count() == 1,
+    }
+}
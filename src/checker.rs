@@ -1,26 +1,226 @@
 use crate::{
+    ast::visit_children_pruned,
+    checker::class::MethodKind,
     environment::Environment,
-    type_var::{Place, TypeVar},
-    visit_all_children,
+    type_var::{ClassType, LiteralValue, Param, ParamKind, Place, TypeVar},
 };
 use colored::Colorize;
 use log::{debug, error, log_enabled};
-use std::{cmp::max, vec};
+use std::{
+    cmp::max,
+    collections::{HashMap, HashSet},
+    vec,
+};
 use tree_sitter::{Node, TreeCursor};
 
-#[derive(Debug, Clone)]
+mod annotation;
+mod class;
+mod declarations;
+mod flow;
+mod inference;
+mod narrowing;
+mod operators;
+
+/// Category of a reported error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrKind {
+    /// Types don't line up
+    Type,
+    /// A name was used that isn't defined
+    Name,
+    /// Valid python that the checker doesn't understand yet
+    Unsupported,
+    /// The syntax tree wasn't shaped like the checker expected
+    Internal,
+}
+
+impl std::fmt::Display for ErrKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Type => write!(f, "type"),
+            Self::Name => write!(f, "name"),
+            Self::Unsupported => write!(f, "unsupported"),
+            Self::Internal => write!(f, "internal"),
+        }
+    }
+}
+
+/// Signatures for the builtins, checked into the builtins scope of every Environment
+const BUILTINS_STUB: &str = include_str!("builtins.pyi");
+
+/// Every name of the `builtins` module, ones missing from the stub are `Any`
+/// rather than undefined
+const BUILTIN_NAMES: &[&str] = &[
+    "__build_class__",
+    "__import__",
+    "ArithmeticError",
+    "AssertionError",
+    "AttributeError",
+    "BaseException",
+    "BaseExceptionGroup",
+    "BlockingIOError",
+    "BrokenPipeError",
+    "BufferError",
+    "BytesWarning",
+    "ChildProcessError",
+    "ConnectionAbortedError",
+    "ConnectionError",
+    "ConnectionRefusedError",
+    "ConnectionResetError",
+    "DeprecationWarning",
+    "EOFError",
+    "Ellipsis",
+    "EncodingWarning",
+    "EnvironmentError",
+    "Exception",
+    "ExceptionGroup",
+    "False",
+    "FileExistsError",
+    "FileNotFoundError",
+    "FloatingPointError",
+    "FutureWarning",
+    "GeneratorExit",
+    "IOError",
+    "ImportError",
+    "ImportWarning",
+    "IndentationError",
+    "IndexError",
+    "InterruptedError",
+    "IsADirectoryError",
+    "KeyError",
+    "KeyboardInterrupt",
+    "LookupError",
+    "MemoryError",
+    "ModuleNotFoundError",
+    "NameError",
+    "None",
+    "NotADirectoryError",
+    "NotImplemented",
+    "NotImplementedError",
+    "OSError",
+    "OverflowError",
+    "PendingDeprecationWarning",
+    "PermissionError",
+    "ProcessLookupError",
+    "PythonFinalizationError",
+    "RecursionError",
+    "ReferenceError",
+    "ResourceWarning",
+    "RuntimeError",
+    "RuntimeWarning",
+    "StopAsyncIteration",
+    "StopIteration",
+    "SyntaxError",
+    "SyntaxWarning",
+    "SystemError",
+    "SystemExit",
+    "TabError",
+    "TimeoutError",
+    "True",
+    "TypeError",
+    "UnboundLocalError",
+    "UnicodeDecodeError",
+    "UnicodeEncodeError",
+    "UnicodeError",
+    "UnicodeTranslateError",
+    "UnicodeWarning",
+    "UserWarning",
+    "ValueError",
+    "Warning",
+    "ZeroDivisionError",
+    "abs",
+    "aiter",
+    "all",
+    "anext",
+    "any",
+    "ascii",
+    "bin",
+    "bool",
+    "breakpoint",
+    "bytearray",
+    "bytes",
+    "callable",
+    "chr",
+    "classmethod",
+    "compile",
+    "complex",
+    "copyright",
+    "credits",
+    "delattr",
+    "dict",
+    "dir",
+    "divmod",
+    "enumerate",
+    "eval",
+    "exec",
+    "exit",
+    "filter",
+    "float",
+    "format",
+    "frozenset",
+    "getattr",
+    "globals",
+    "hasattr",
+    "hash",
+    "help",
+    "hex",
+    "id",
+    "input",
+    "int",
+    "isinstance",
+    "issubclass",
+    "iter",
+    "len",
+    "license",
+    "list",
+    "locals",
+    "map",
+    "max",
+    "memoryview",
+    "min",
+    "next",
+    "object",
+    "oct",
+    "open",
+    "ord",
+    "pow",
+    "print",
+    "property",
+    "quit",
+    "range",
+    "repr",
+    "reversed",
+    "round",
+    "set",
+    "setattr",
+    "slice",
+    "sorted",
+    "staticmethod",
+    "str",
+    "sum",
+    "super",
+    "tuple",
+    "type",
+    "vars",
+    "zip",
+];
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CheckErr {
+    kind: ErrKind,
     msg: String,
     start_place: Place,
     end_place: Option<Place>,
+    /// related place printed after the error, eg. the method being overridden
+    note: Option<Box<CheckErr>>,
 }
 
 impl std::fmt::Display for CheckErr {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "CheckErr: {} @ {} to {:?}",
-            self.msg, self.start_place, self.end_place
+            "CheckErr({}): {} @ {} to {:?}",
+            self.kind, self.msg, self.start_place, self.end_place
         )
     }
 }
@@ -30,241 +230,874 @@ impl std::error::Error for CheckErr {}
 impl CheckErr {
     pub fn new(msg: &str, start_place: Place, end_place: Option<Place>) -> Self {
         CheckErr {
+            kind: ErrKind::Type,
             msg: msg.to_owned(),
             start_place,
             end_place,
+            note: None,
         }
     }
 
     pub fn new_from_node(msg: &str, n: &tree_sitter::Node) -> Self {
         CheckErr {
+            kind: ErrKind::Type,
             msg: msg.to_owned(),
             start_place: Place::from_ts_point("start", n.start_position()),
             end_place: Some(Place::from_ts_point("end", n.end_position())),
+            note: None,
         }
     }
+
+    pub fn with_kind(mut self, kind: ErrKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_note(mut self, msg: &str, place: Place) -> Self {
+        self.note = Some(Box::new(CheckErr::new(msg, place, None)));
+        self
+    }
+
+    /// Error for a name that isn't defined in any live scope
+    pub fn undefined_name(name: &str, n: &tree_sitter::Node) -> Self {
+        Self::new_from_node(&format!("name '{}' is not defined", name), n).with_kind(ErrKind::Name)
+    }
+
+    /// Error for a var read where some paths reaching it don't assign it
+    pub fn possibly_unbound(name: &str, n: &tree_sitter::Node) -> Self {
+        Self::new_from_node(&format!("name '{}' is possibly unbound", name), n)
+            .with_kind(ErrKind::Name)
+    }
+
+    /// Error for a local var read before any assignment, python raises `UnboundLocalError`
+    pub fn unbound_local(name: &str, n: &tree_sitter::Node) -> Self {
+        Self::new_from_node(
+            &format!("local variable '{}' referenced before assignment", name),
+            n,
+        )
+        .with_kind(ErrKind::Name)
+    }
+
+    /// Error for a construct the checker can't handle yet
+    pub fn unsupported(msg: &str, n: &tree_sitter::Node) -> Self {
+        Self::new_from_node(msg, n).with_kind(ErrKind::Unsupported)
+    }
+
+    /// Error for a syntax tree the checker didn't expect
+    pub fn internal(msg: &str, n: &tree_sitter::Node) -> Self {
+        Self::new_from_node(msg, n).with_kind(ErrKind::Internal)
+    }
+}
+
+/// What calling a value looks like
+enum CallSig {
+    /// Parameters to check the arguments against and the type of the result
+    Known(Vec<Param>, TypeVar),
+    /// The value could be called with anything, the result has this type
+    Unknown(TypeVar),
+    NotCallable,
+}
+
+/// Return statements found in the body of the function being checked
+struct ReturnContext {
+    /// Type of the return annotation, `None` when the return type is infered
+    expected: Option<TypeVar>,
+    found: Vec<TypeVar>,
+    /// The end of the body can be reached, implicitly returning `None`
+    falls_through: bool,
+}
+
+/// Value of an integer literal like `1_000` or `0xff`, `None` when it's too big to track
+pub(crate) fn parse_int(text: &str) -> Option<i128> {
+    let int_str = text.replace('_', "");
+    match int_str.get(..2) {
+        Some("0x" | "0X") => i128::from_str_radix(&int_str[2..], 16).ok(),
+        Some("0o" | "0O") => i128::from_str_radix(&int_str[2..], 8).ok(),
+        Some("0b" | "0B") => i128::from_str_radix(&int_str[2..], 2).ok(),
+        _ => int_str.parse().ok(),
+    }
 }
 
 pub struct Checker<'a> {
     //_env: HashMap<String, Place>,
     env: Environment,
     errors: Vec<CheckErr>,
+    /// one context per function currently being checked, innermost last
+    returns: Vec<ReturnContext>,
+    /// class whose body is being checked, definitions here are class attributes
+    current_class: Option<ClassType>,
+    /// class of the method being checked, `self.x = ...` defines attributes on it
+    method_class: Option<ClassType>,
+    /// how methods are bound, keyed by the place of the method definition
+    method_kinds: HashMap<Place, MethodKind>,
+    /// places joining paths where the var they hold isn't always assigned
+    possibly_unbound: HashSet<Place>,
+    /// functions and classes declared before the statement defining them has been checked
+    declared_ahead: HashSet<Place>,
+    /// `self` attributes assigned in more than one place, keyed by the place declaring them
+    reassigned_attrs: HashSet<Place>,
+    /// functions without a return annotation, their return type is inferred from the body
+    inferred_returns: HashSet<Place>,
+    /// type of the elements each `for` loop assigns, keyed by the id of the loop node
+    loop_elements: HashMap<usize, TypeVar>,
+    /// unannotated parameters get type variables solved from how they are used
+    infer_params: bool,
+    /// uses of type variables in the functions being checked
+    constraints: Vec<inference::Constraint>,
     src: &'a str,
     file_name: &'a str,
 }
 
 impl<'a> Checker<'a> {
     pub fn new(src: &'a str, file_name: &'a str) -> Self {
+        let mut env = Environment::new(file_name);
+        env.set_builtins(&Checker::builtins_env());
+        Self::with_env(env, src, file_name)
+    }
+
+    /// Checker for `src` that defines names in `env`
+    fn with_env(env: Environment, src: &'a str, file_name: &'a str) -> Self {
         Checker {
-            env: Environment::new(file_name),
+            env,
             errors: Vec::<CheckErr>::new(),
+            returns: Vec::new(),
+            current_class: None,
+            method_class: None,
+            method_kinds: HashMap::new(),
+            possibly_unbound: HashSet::new(),
+            declared_ahead: HashSet::new(),
+            reassigned_attrs: HashSet::new(),
+            inferred_returns: HashSet::new(),
+            loop_elements: HashMap::new(),
+            infer_params: false,
+            constraints: Vec::new(),
             src,
             file_name,
         }
     }
 
+    /// Check the bundled builtins stub, the returned Environment holds its definitions
+    fn builtins_env() -> Environment {
+        let env = Environment::new("builtins");
+        let mut stub_checker = Checker::with_env(env, BUILTINS_STUB, "builtins.pyi");
+        if let Some(tree) = crate::ast::parse(BUILTINS_STUB) {
+            stub_checker.check_block(&tree.root_node());
+        }
+        for err in &stub_checker.errors {
+            error!("builtins stub {}", err);
+        }
+        stub_checker.env
+    }
+
     pub fn check_module(&mut self, cursor: &mut TreeCursor) {
         println!("Checking {}...", self.file_name);
-        visit_all_children(cursor, &mut |cur| {
-            self.check_visit(cur);
-        });
+        let names = self.local_names(&cursor.node());
+        self.env.set_local_names(names);
+        self.check_block(&cursor.node());
         if log_enabled!(log::Level::Debug) {
             self.env.pretty_print();
         }
         self.print_errors();
     }
 
-    pub fn check_visit(&mut self, cursor: &mut TreeCursor) {
+    /// Record an error, the same error found twice is only reported once
+    pub fn report(&mut self, err: CheckErr) {
+        if !self.errors.contains(&err) {
+            self.errors.push(err);
+        }
+    }
+
+    /// Get a required child of `node` by field name
+    pub fn child<'t>(&self, node: &Node<'t>, field: &str) -> Result<Node<'t>, CheckErr> {
+        node.child_by_field_name(field).ok_or_else(|| {
+            CheckErr::internal(&format!("missing `{}` in {}", field, node.kind()), node)
+        })
+    }
+
+    /// Source text for a node
+    pub fn node_text(&self, node: &Node) -> Result<&'a str, CheckErr> {
+        node.utf8_text(self.src.as_bytes())
+            .map_err(|_| CheckErr::internal("couldnt decode source text", node))
+    }
+
+    /// Contents of a plain string literal, `None` for f-strings and implicit concatenation
+    /// Escape sequences are kept as written
+    fn string_value(&self, node: &Node) -> Option<String> {
+        if node.kind() != "string" {
+            return None;
+        }
+        let mut cursor = node.walk();
+        let mut value = String::new();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "string_start" | "string_end" => {}
+                "string_content" => value.push_str(self.node_text(&child).ok()?),
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+
+    /// Infer the type of `node`, reporting any error and falling back to `Any`
+    pub fn infer_or_any(&mut self, node: &Node) -> TypeVar {
+        self.infer_type_for_node(node).unwrap_or_else(|err| {
+            self.report(err);
+            TypeVar::Any
+        })
+    }
+
+    /// Check `node` and everything under it
+    pub fn check_node(&mut self, node: &Node) {
+        visit_children_pruned(&mut node.walk(), &mut |cur| self.check_visit(cur));
+    }
+
+    /// Check a single node
+    /// Returns `false` when the node already handled its children and the walk should skip them
+    pub fn check_visit(&mut self, cursor: &mut TreeCursor) -> bool {
         match cursor.node().kind() {
             "expression_statement" => {
                 debug!("EXPR_STMT   -");
             }
             "assignment" => {
-                debug!(
-                    "DEFINE      - {}",
-                    cursor.node().child_by_field_name("left").unwrap()
-                );
+                debug!("DEFINE      - {}", cursor.node());
                 self.check_assignment(cursor).unwrap_or_else(|err| {
-                    self.errors.push(err);
+                    self.report(err);
                 });
             }
             "binary_operator" => {
                 self.check_binop(cursor).unwrap_or_else(|err| {
                     debug!("Type Error {}", err);
-                    self.errors.push(err);
+                    self.report(err);
                 });
             }
+            "augmented_assignment" => {
+                self.check_augmented_assignment(&cursor.node());
+            }
             "function_definition" => {
-                self.check_function_def(cursor);
+                // the body is checked inside the function scope
+                self.check_function_def(cursor).unwrap_or_else(|err| {
+                    self.report(err);
+                });
+                return false;
+            }
+            "class_definition" => {
+                // the body is checked inside the class scope
+                self.check_class_def(&cursor.node()).unwrap_or_else(|err| {
+                    self.report(err);
+                });
+                return false;
             }
             "call" => {
                 self.check_fn_call(cursor).unwrap_or_else(|err| {
-                    self.errors.push(err);
+                    self.report(err);
                 });
             }
+            // the targets of a comprehension are bound in its own scope before its body is checked
+            "list_comprehension"
+            | "set_comprehension"
+            | "dictionary_comprehension"
+            | "generator_expression" => {
+                self.infer_or_any(&cursor.node());
+                return false;
+            }
+            // reports attributes that don't exist and unsupported operands,
+            // `(n := v)` binds `n` as it is inferred
+            "attribute" | "comparison_operator" | "unary_operator" | "named_expression" => {
+                self.infer_or_any(&cursor.node());
+            }
+            "return_statement" => {
+                self.check_return(&cursor.node());
+            }
+            "import_statement" | "import_from_statement" => {
+                self.bind_import(&cursor.node());
+                return false;
+            }
+            // the body is checked inside the lambda scope
+            "lambda" => {
+                self.infer_or_any(&cursor.node());
+                return false;
+            }
+            // statements that branch are checked by following their control flow
+            // most are already split up by the block they are in, eg. not inside a `match`
+            "if_statement" | "while_statement" | "for_statement" | "try_statement"
+            | "with_statement" => {
+                self.check_flow(&[cursor.node()]);
+                return false;
+            }
+            // annotations are evaluated as types by whatever owns them
+            // and decorators are handled by the function they decorate
+            "type" | "decorator" => return false,
             "module" => {} // nodes to ignore
             _ => {
                 debug!("UNSEEN NODE - {} {}", cursor.node(), cursor.node().kind());
             }
         }
+        true
     }
 
-    pub fn infer_type_for_node(&mut self, node: &tree_sitter::Node) -> Option<TypeVar> {
+    pub fn infer_type_for_node(&mut self, node: &tree_sitter::Node) -> Result<TypeVar, CheckErr> {
         let inferred_node_type = match node.kind() {
             "identifier" => {
-                let node_id = node
-                    .utf8_text(self.src.as_bytes())
-                    .expect("couldnt decode id");
-                self.env
-                    .var_type(node_id)
-                    .expect(&format!("couldnt find type for var {}", node_id))
-            }
-            "call" => {
-                let sig = self.infer_type_for_node(
-                    &(node
-                        .child_by_field_name("function")
-                        .expect("getting fn name")),
-                )?;
-                if let TypeVar::Function(_, _, ret_val) = sig {
-                    if ret_val.len() == 1 {
-                        ret_val.first().cloned()?
-                    } else {
-                        TypeVar::Union(ret_val)
+                let node_id = self.node_text(node)?;
+                if self.env.is_unassigned_local(node_id) {
+                    // python doesn't look in outer scopes for a local name
+                    self.report(CheckErr::unbound_local(node_id, node));
+                    TypeVar::Any
+                } else if let Some(ty) = self.env.var_type(node_id) {
+                    if self.is_declared_ahead(node_id) {
+                        // only code that runs later, like function bodies, can use it yet
+                        let err = match self.returns.is_empty() {
+                            true => CheckErr::undefined_name(node_id, node),
+                            false => CheckErr::unbound_local(node_id, node),
+                        };
+                        self.report(err);
+                    } else if self.is_possibly_unbound(node_id) {
+                        self.report(CheckErr::possibly_unbound(node_id, node));
                     }
+                    ty
+                } else if self.env.is_enclosing_local(node_id) {
+                    // assigned later around a function, which usually runs after that
+                    TypeVar::Any
+                } else if BUILTIN_NAMES.contains(&node_id) {
+                    TypeVar::Any
                 } else {
-                    TypeVar::None
+                    // keep checking the rest of the module as if the name was untyped
+                    self.report(CheckErr::undefined_name(node_id, node));
+                    TypeVar::Any
+                }
+            }
+            "call" => {
+                let fn_node = self.child(node, "function")?;
+                let callee = self.infer_type_for_node(&fn_node)?;
+                let mut results = Vec::new();
+                for member in callee.members() {
+                    let result = match self.call_signature(&member) {
+                        // generic results depend on the arguments
+                        CallSig::Known(params, ret) if ret.contains_var() => {
+                            let fn_name = self.node_text(&fn_node)?;
+                            let args = self.call_arg_nodes(node)?;
+                            let bindings = self.check_call_args(fn_name, &params, &args, node);
+                            Self::instantiate(&ret, &bindings)
+                        }
+                        CallSig::Known(_, ret) | CallSig::Unknown(ret) => ret,
+                        CallSig::NotCallable => TypeVar::Any,
+                    };
+                    results.push(result);
                 }
+                TypeVar::union(results)
+            }
+            "subscript" => {
+                let value = self.infer_type_for_node(&self.child(node, "value")?)?;
+                let index = self.child(node, "subscript")?;
+                self.subscript_type(&value, &index)?
+            }
+            "attribute" => self.infer_attribute(node)?,
+            "named_expression" => {
+                let ty = self.infer_type_for_node(&self.child(node, "value")?)?;
+                self.bind_target(&self.child(node, "name")?, &ty);
+                ty
             }
-            "integer" => {
-                let int_val: usize = node
-                    .utf8_text(self.src.as_bytes())
-                    .map(|i| i.parse().expect("error parsing"))
-                    .expect("issue getting int value");
-                TypeVar::Integer(int_val)
+            "comparison_operator" => self.infer_comparison(node)?,
+            "not_operator" => TypeVar::Bool(),
+            "boolean_operator" => self.infer_boolean_op(node)?,
+            "unary_operator" => self.infer_unary(node)?,
+            "integer" | "float" if self.node_text(node)?.ends_with(['j', 'J']) => {
+                TypeVar::Complex()
+            }
+            // ints too big to track as a constant are still ints
+            "integer" => match parse_int(self.node_text(node)?) {
+                Some(value) => TypeVar::Literal(LiteralValue::Int(value)),
+                None => TypeVar::Integer(),
+            },
+            "float" => TypeVar::Float(),
+            "true" => TypeVar::Literal(LiteralValue::Bool(true)),
+            "false" => TypeVar::Literal(LiteralValue::Bool(false)),
+            "string" | "concatenated_string" => {
+                // the prefix before the first quote decides if this is a byte string
+                let text = self.node_text(node)?;
+                let prefix = text.split(['"', '\'']).next().unwrap_or_default();
+                if prefix.contains(['b', 'B']) {
+                    TypeVar::Bytes()
+                } else {
+                    match self.string_value(node) {
+                        Some(value) => TypeVar::Literal(LiteralValue::Str(value)),
+                        None => TypeVar::String(),
+                    }
+                }
             }
-            "string" => TypeVar::String(),
             "return_statement" => {
                 if let Some(n) = node.named_child(0) {
-                    self.infer_type_for_node(&n)
-                        .expect("invalid return statement")
+                    self.infer_type_for_node(&n)?
                 } else {
                     TypeVar::None
                 }
             }
-            "binary_operator" => {
-                TypeVar::BinOp(Place::from_ts_point("binop", node.start_position()))
-            }
-            "typed_parameter" => {
-                let type_str = node
-                    .child_by_field_name("type")
-                    .and_then(|n| n.utf8_text(self.src.as_bytes()).ok())
-                    .unwrap();
-                TypeVar::from_type_str(type_str).expect("error getting type")
-            },
+            "binary_operator" | "augmented_assignment" => self.infer_binop(node)?,
+            "typed_parameter" | "typed_default_parameter" => {
+                self.annotation_type(&self.child(node, "type")?)?
+            }
             "none" => TypeVar::None,
+            // `...` is mostly used as a placeholder, eg. for defaults in stubs
+            "ellipsis" => TypeVar::Any,
+            "lambda" => self.infer_lambda(node)?,
+            "list" => TypeVar::List(Box::new(self.infer_element_union(node)?)),
+            "set" => TypeVar::Set(Box::new(self.infer_element_union(node)?)),
+            "tuple" | "expression_list" => TypeVar::Tuple(self.infer_elements(node)?),
+            "parenthesized_expression" => match node.named_child(0) {
+                Some(inner) => self.infer_type_for_node(&inner)?,
+                None => TypeVar::Tuple(vec![]),
+            },
+            "dictionary" => {
+                let mut key_types = Vec::new();
+                let mut value_types = Vec::new();
+                for pair in node.named_children(&mut node.walk()) {
+                    match pair.kind() {
+                        "pair" => {
+                            key_types.push(self.infer_type_for_node(&self.child(&pair, "key")?)?);
+                            value_types
+                                .push(self.infer_type_for_node(&self.child(&pair, "value")?)?);
+                        }
+                        "comment" => {}
+                        // `**other` could hold anything
+                        _ => {
+                            key_types.push(TypeVar::Any);
+                            value_types.push(TypeVar::Any);
+                        }
+                    }
+                }
+                TypeVar::Dict(
+                    Box::new(Self::join_elements(key_types)),
+                    Box::new(Self::join_elements(value_types)),
+                )
+            }
+            "list_comprehension" => TypeVar::List(Box::new(Self::join_elements(
+                self.infer_comprehension(node)?,
+            ))),
+            "set_comprehension" => TypeVar::Set(Box::new(Self::join_elements(
+                self.infer_comprehension(node)?,
+            ))),
+            "dictionary_comprehension" => match self.infer_comprehension(node)?.as_slice() {
+                [key, value] => TypeVar::Dict(Box::new(key.widened()), Box::new(value.widened())),
+                _ => TypeVar::Dict(Box::new(TypeVar::Any), Box::new(TypeVar::Any)),
+            },
+            // generators can't be described yet
+            "generator_expression" => {
+                self.infer_comprehension(node)?;
+                TypeVar::Any
+            }
 
             _ => TypeVar::Var(Place::exp_from_ts_point(node.start_position())),
         };
-        Some(inferred_node_type)
-    }
-
-    pub fn infer_fn_body(&mut self, node: &tree_sitter::Node, allowed_types: Option<Vec<TypeVar>>) -> Vec<TypeVar> {
-        let mut return_statement_types: Vec<TypeVar> = Vec::new();
-
-        visit_all_children(&mut node.walk(), &mut |c| {
-            if c.node().kind() == "return_statement" {
-                debug!("{}", c.node());
-                let return_type = self.infer_type_for_node(&c.node())
-                        .expect("error infering return");
-                if let Some(allowed) = &allowed_types {
-                    if !allowed.contains(&return_type) {
-                        self.errors.push(
-                            CheckErr::new_from_node(
-                                &format!("Unexpected return type {}, fn signature return {:?}", return_type, allowed), &c.node())
-                        );
+        Ok(inferred_node_type)
+    }
+
+    /// Types of each element in a list, set or tuple literal
+    fn infer_elements(&mut self, node: &Node) -> Result<Vec<TypeVar>, CheckErr> {
+        let mut elem_types = Vec::new();
+        for elem in node.named_children(&mut node.walk()) {
+            match elem.kind() {
+                "comment" => {}
+                // unpacking `*other` could add anything
+                "list_splat" => elem_types.push(TypeVar::Any),
+                _ => elem_types.push(self.infer_type_for_node(&elem)?),
+            }
+        }
+        Ok(elem_types)
+    }
+
+    /// Types a comprehension produces, the key and value for a dict and the element otherwise
+    /// The targets of its `for` clauses are bound in a scope of its own
+    fn infer_comprehension(&mut self, node: &Node) -> Result<Vec<TypeVar>, CheckErr> {
+        let place = Place::from_ts_point("comprehension", node.start_position());
+        let _scope_guard = self.env.enter_scope(&place.to_string());
+        for clause in node.named_children(&mut node.walk()) {
+            match clause.kind() {
+                "for_in_clause" => {
+                    let iterable = self.child(&clause, "right")?;
+                    self.check_node(&iterable);
+                    let ty = self.infer_or_any(&iterable);
+                    let element = self.element_type_or_any(&ty, &iterable);
+                    self.bind_target(&self.child(&clause, "left")?, &element);
+                }
+                "if_clause" => {
+                    for cond in clause.named_children(&mut clause.walk()) {
+                        self.check_node(&cond);
                     }
                 }
-                
-                return_statement_types.push(return_type)
-            };
+                _ => {}
+            }
+        }
+        let body = self.child(node, "body")?;
+        self.check_node(&body);
+        let parts = match body.kind() {
+            "pair" => vec![self.child(&body, "key")?, self.child(&body, "value")?],
+            _ => vec![body],
+        };
+        Ok(parts.iter().map(|part| self.infer_or_any(part)).collect())
+    }
+
+    /// Whether the value of `node` with type `actual` can be used where `expected` is
+    /// A container literal is new so only its elements have to fit, eg. `[1]` is a `list[float]`
+    fn value_fits(expected: &TypeVar, node: &Node, actual: &TypeVar) -> bool {
+        let fresh = |kinds: &[&str]| kinds.contains(&node.kind());
+        match (expected, actual) {
+            (TypeVar::List(e), TypeVar::List(a)) if fresh(&["list", "list_comprehension"]) => {
+                e.type_check(a)
+            }
+            (TypeVar::Set(e), TypeVar::Set(a)) if fresh(&["set", "set_comprehension"]) => {
+                e.type_check(a)
+            }
+            (TypeVar::Dict(ek, ev), TypeVar::Dict(ak, av))
+                if fresh(&["dictionary", "dictionary_comprehension"]) =>
+            {
+                ek.type_check(ak) && ev.type_check(av)
+            }
+            (TypeVar::Union(members), _) if !matches!(actual, TypeVar::Union(_)) => {
+                members.iter().any(|m| Self::value_fits(m, node, actual))
+            }
+            _ => expected.type_check(actual),
+        }
+    }
+
+    /// Element type of a list or set literal, differing elements are joined into a union
+    fn infer_element_union(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let elem_types = self.infer_elements(node)?;
+        Ok(Self::join_elements(elem_types))
+    }
+
+    /// Join element types into a single type, an empty container could hold anything
+    /// Elements of mutable containers can be replaced so constants are widened
+    fn join_elements(elem_types: Vec<TypeVar>) -> TypeVar {
+        if elem_types.is_empty() {
+            TypeVar::Any
+        } else {
+            TypeVar::union(elem_types).widened()
+        }
+    }
+
+    /// Check the body of a function and collect the types it returns
+    fn infer_fn_body(&mut self, node: &Node, expected: Option<TypeVar>) -> ReturnContext {
+        self.returns.push(ReturnContext {
+            expected,
+            found: Vec::new(),
+            falls_through: false,
         });
-        match return_statement_types.len() {
-            0 => vec![TypeVar::None],
-            _ => return_statement_types,
+        let falls_through = self.check_block(node);
+        let mut ctx = self.returns.pop().expect("pushed before checking the body");
+        ctx.falls_through = falls_through;
+        if falls_through {
+            ctx.found.push(TypeVar::None);
+        }
+        ctx
+    }
+
+    /// Record the type of a return statement for the function being checked
+    pub fn check_return(&mut self, node: &Node) {
+        debug!("{}", node);
+        let return_type = self.infer_or_any(node);
+        let Some(ctx) = self.returns.last_mut() else {
+            self.report(CheckErr::new_from_node("'return' outside function", node));
+            return;
+        };
+        ctx.found.push(return_type.widened());
+        let Some(expected) = ctx.expected.clone() else {
+            return;
+        };
+        self.constrain(&expected, &return_type, node);
+        let value = node.named_child(0).unwrap_or(*node);
+        if !Self::value_fits(&expected, &value, &return_type) {
+            let err = CheckErr::new_from_node(
+                &format!(
+                    "Incompatible return type expected {} found {}",
+                    expected, return_type
+                ),
+                node,
+            );
+            self.report(err);
         }
     }
 
-    pub fn check_function_def(&mut self, cursor: &mut TreeCursor) {
-        let mut param_types: Vec<TypeVar> = Vec::new();
+    /// Bodies of stubs and protocols like `...` or just a docstring never run,
+    /// neither do those of `@abstractmethod` and `@overload` functions, which can also be `pass`
+    fn is_placeholder_body(&self, fn_node: &Node, block: &Node) -> bool {
+        let declaration_only = self.has_decorator(fn_node, &["abstractmethod", "overload"]);
+        block
+            .named_children(&mut block.walk())
+            .all(|stmt| match stmt.kind() {
+                "comment" => true,
+                "pass_statement" => declaration_only,
+                "expression_statement" => stmt
+                    .named_child(0)
+                    .is_some_and(|e| matches!(e.kind(), "ellipsis" | "string")),
+                _ => false,
+            })
+    }
 
-        let fn_name = cursor
-            .node()
-            .child_by_field_name("name")
-            .and_then(|n| n.utf8_text(self.src.as_bytes()).ok())
-            .expect("no fn name");
-        let fn_place = Place::from_ts_point(fn_name, cursor.node().start_position());
+    /// Whether a function is decorated by one of `names`, also through a module like `abc.abstractmethod`
+    fn has_decorator(&self, fn_node: &Node, names: &[&str]) -> bool {
+        let Some(decorated) = fn_node
+            .parent()
+            .filter(|p| p.kind() == "decorated_definition")
+        else {
+            return false;
+        };
+        decorated
+            .named_children(&mut decorated.walk())
+            .filter(|d| d.kind() == "decorator")
+            .filter_map(|d| self.node_text(&d).ok())
+            .any(|text| {
+                let name = text.trim_start_matches('@').trim();
+                names.contains(&name.rsplit('.').next().unwrap_or(name))
+            })
+    }
 
-        let param_node = cursor
-            .node()
-            .child_by_field_name("parameters")
-            .expect("no parameters");
+    /// Node holding the name of a parameter, `None` for the bare `*` and `/` separators
+    fn param_name_node<'t>(&self, node: &Node<'t>) -> Option<Node<'t>> {
+        match node.kind() {
+            "identifier" => Some(*node),
+            "default_parameter" | "typed_default_parameter" => node.child_by_field_name("name"),
+            // typed parameters, `*args` and `**kwargs` hold the name as their first child
+            "typed_parameter" | "list_splat_pattern" | "dictionary_splat_pattern" => {
+                node.named_child(0).and_then(|n| self.param_name_node(&n))
+            }
+            _ => None,
+        }
+    }
 
-        let body_node = cursor
-            .node()
-            .child_by_field_name("body")
-            .expect("error getting fn body");
+    /// Splat patterns are `*args` and `**kwargs`, other parameters can be passed normally
+    fn splat_kind(node: &Node) -> Option<ParamKind> {
+        let pattern = match node.kind() {
+            "typed_parameter" => node.named_child(0)?,
+            _ => *node,
+        };
+        match pattern.kind() {
+            "list_splat_pattern" => Some(ParamKind::VarPositional),
+            "dictionary_splat_pattern" => Some(ParamKind::VarKeyword),
+            _ => None,
+        }
+    }
 
-        let _scope_guard = self.env.enter_scope(fn_name);
+    /// Each named parameter with its name node and how it can be passed
+    /// The bare `*` and `/` separators change the kinds of the parameters around them
+    fn param_kinds<'t>(&mut self, param_node: &Node<'t>) -> Vec<(Node<'t>, Node<'t>, ParamKind)> {
+        let mut kinds = Vec::new();
+        // set after `*` or `*args`
+        let mut keyword_only = false;
         for node in param_node.named_children(&mut param_node.walk()) {
-            let p_type = if node.kind() == "typed_parameter" {
-                self.infer_type_for_node(&node)
-                    .expect("error getting param type")
+            let Some(id_node) = self.param_name_node(&node) else {
+                match node.kind() {
+                    "keyword_separator" => keyword_only = true,
+                    // everything before `/` is positional only
+                    "positional_separator" => {
+                        for (_, _, kind) in
+                            kinds.iter_mut().filter(|(_, _, k)| *k == ParamKind::Normal)
+                        {
+                            *kind = ParamKind::PositionalOnly;
+                        }
+                    }
+                    "comment" => {}
+                    _ => self.report(CheckErr::unsupported(
+                        &format!("unsupported parameter {}", node.kind()),
+                        &node,
+                    )),
+                }
+                continue;
+            };
+            let p_kind = match Self::splat_kind(&node) {
+                Some(splat) => {
+                    keyword_only = true;
+                    splat
+                }
+                None if keyword_only => ParamKind::KeywordOnly,
+                None => ParamKind::Normal,
+            };
+            kinds.push((node, id_node, p_kind));
+        }
+        kinds
+    }
+
+    /// Type of the first parameter of a method, the instance or class it is bound to
+    fn bound_param_type(owner: &ClassType, kind: MethodKind) -> TypeVar {
+        match kind {
+            MethodKind::Static => TypeVar::Any,
+            MethodKind::Class => TypeVar::Class(owner.clone()),
+            _ => TypeVar::Instance(owner.clone()),
+        }
+    }
+
+    /// Bind the parameters of a function or lambda in the current scope
+    /// `owner` is the class of a method, its first parameter is bound to the class or instance
+    /// Type variables made for unannotated parameters are added to `vars`
+    fn check_params(
+        &mut self,
+        param_node: &Node,
+        owner: Option<&ClassType>,
+        kind: MethodKind,
+        vars: &mut Vec<Place>,
+    ) -> Result<Vec<Param>, CheckErr> {
+        let mut params: Vec<Param> = Vec::new();
+        for (node, id_node, p_kind) in self.param_kinds(param_node) {
+            let has_default = node.child_by_field_name("value").is_some();
+            let p_id = self.node_text(&id_node)?;
+            let param_place = Place::from_ts_point(p_id, node.start_position());
+            let p_type = if node.child_by_field_name("type").is_some() {
+                self.infer_or_any(&node)
+            } else if let Some(cls) = owner
+                && params.is_empty()
+                && p_kind == ParamKind::Normal
+            {
+                Self::bound_param_type(cls, kind)
+            } else if self.infer_params && Self::splat_kind(&node).is_none() {
+                vars.push(param_place.clone());
+                TypeVar::Var(param_place.clone())
             } else {
                 TypeVar::Any
             };
 
-            param_types.push(p_type.clone());
-            let p_id = node
-                .utf8_text(self.src.as_bytes())
-                .expect("error getting param id");
-            let param_place = Place::from_ts_point(p_id, node.start_position());
-            self.env.insert_binding(param_place.clone(), p_type.clone());
+            if let Some(default) = node.child_by_field_name("value") {
+                let default_type = self.infer_or_any(&default);
+                self.constrain(&p_type, &default_type, &default);
+                if !p_type.type_check(&default_type) {
+                    self.report(CheckErr::new_from_node(
+                        &format!(
+                            "Default for parameter expected {} found {}",
+                            p_type, default_type
+                        ),
+                        &default,
+                    ));
+                }
+            }
+
+            // the annotation of `*args` and `**kwargs` is the type of each extra argument
+            let binding_type = match p_kind {
+                ParamKind::VarPositional => TypeVar::Any,
+                ParamKind::VarKeyword => {
+                    TypeVar::Dict(Box::new(TypeVar::String()), Box::new(p_type.clone()))
+                }
+                _ => p_type.clone(),
+            };
+            self.env.insert_binding(param_place.clone(), binding_type);
             self.env.insert_var(p_id, param_place.clone());
+            params.push(Param {
+                name: p_id.to_owned(),
+                kind: p_kind,
+                has_default,
+                ty: p_type,
+            });
         }
-        
-        let return_type = if let Some(explicit_return_type) = cursor.node().child_by_field_name("return_type") {
-            let ty_str = explicit_return_type.utf8_text(self.src.as_bytes()).unwrap();
-            debug!("return type {} for fn {}", ty_str, fn_name);
-            let ty = vec![TypeVar::from_type_str(ty_str).expect("couldnt get type")];
-            
-            self.infer_fn_body(&body_node, Some(ty.clone()));
-            ty
-        } else {
-            debug!("infering body for fn {}", fn_name);
-            self.infer_fn_body(&body_node, None)
+        Ok(params)
+    }
+
+    /// Type of a `lambda`, its body is checked in its own scope
+    fn infer_lambda(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let constraint_start = self.constraints.len();
+        let mut vars = Vec::new();
+        let place = Place::from_ts_point("lambda", node.start_position());
+        let _scope_guard = self.env.enter_scope(&place.to_string());
+        let params = match node.child_by_field_name("parameters") {
+            Some(param_node) => {
+                self.check_params(&param_node, None, MethodKind::Static, &mut vars)?
+            }
+            None => Vec::new(),
+        };
+        let body = self.infer_type_for_node(&self.child(node, "body")?)?;
+        drop(_scope_guard);
+        let (params, ret) =
+            self.solve_signature(constraint_start, &vars, params, vec![body.widened()]);
+        Ok(TypeVar::Function(place, params, ret))
+    }
+
+    pub fn check_function_def(&mut self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
+        let constraint_start = self.constraints.len();
+        let mut vars: Vec<Place> = Vec::new();
+
+        let fn_node = cursor.node();
+        let fn_name = self.node_text(&self.child(&fn_node, "name")?)?;
+        let fn_place = Place::from_ts_point(fn_name, fn_node.start_position());
+
+        let param_node = self.child(&fn_node, "parameters")?;
+        let body_node = self.child(&fn_node, "body")?;
+
+        // functions defined directly in a class body are methods
+        let owner = self.current_class.take();
+        let kind = match owner {
+            Some(_) => self.method_kind(&fn_node),
+            None => MethodKind::Static,
         };
+        let outer_method_class = std::mem::replace(&mut self.method_class, owner.clone());
+
+        let _scope_guard = self.env.enter_scope(&fn_place.to_string());
+        // start without the vars from checking the body before, eg. in an earlier loop pass
+        self.env.set_local_vars(HashMap::new());
+        self.env.set_local_names(self.local_names(&body_node));
+        let params = self.check_params(&param_node, owner.as_ref(), kind, &mut vars)?;
+
+        let return_type =
+            if let Some(explicit_return_type) = fn_node.child_by_field_name("return_type") {
+                debug!("return type {} for fn {}", explicit_return_type, fn_name);
+                match self.annotation_type(&explicit_return_type) {
+                    Ok(ty) => {
+                        let ctx = self.infer_fn_body(&body_node, Some(ty.clone()));
+                        // falling off the end returns None
+                        if ctx.falls_through
+                            && !ty.type_check(&TypeVar::None)
+                            && !self.is_placeholder_body(&fn_node, &body_node)
+                        {
+                            self.report(CheckErr::new_from_node(
+                                "Missing return statement",
+                                &explicit_return_type,
+                            ));
+                        }
+                        vec![ty]
+                    }
+                    Err(err) => {
+                        self.report(err);
+                        self.infer_fn_body(&body_node, None);
+                        vec![TypeVar::Any]
+                    }
+                }
+            } else {
+                debug!("infering body for fn {}", fn_name);
+                self.inferred_returns.insert(fn_place.clone());
+                self.infer_returns(&body_node, &fn_place, &params)
+            };
         debug!("Handling fn {} {}", fn_name, param_node);
         drop(_scope_guard); //leave function scope
-        self.env.insert_binding(
-            fn_place.clone(),
-            TypeVar::Function(fn_place.clone(), param_types, return_type),
-        );
+
+        let (params, return_type) =
+            self.solve_signature(constraint_start, &vars, params, return_type);
+        self.method_class = outer_method_class;
+        self.current_class = owner;
+
+        let fn_type = match kind {
+            // setters don't replace the property they belong to
+            MethodKind::PropertyAccessor => return Ok(()),
+            MethodKind::Property => TypeVar::union(return_type),
+            _ => TypeVar::Function(fn_place.clone(), params, return_type),
+        };
+        if let Some(cls) = self.current_class.clone() {
+            if kind != MethodKind::Instance {
+                self.method_kinds.insert(fn_place.clone(), kind);
+            }
+            let name_node = self.child(&fn_node, "name")?;
+            self.check_override(&cls, fn_name, &fn_type, &name_node);
+        }
+        self.env.insert_binding(fn_place.clone(), fn_type);
         self.env.insert_var(fn_name, fn_place.clone());
+        self.declared_ahead.remove(&fn_place);
+        Ok(())
     }
 
     /// Handle reveal_type similar to other type checkers
-    /// Print the type for the variable
-    pub fn call_reveal_type(&self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
-        let fn_args_list = cursor
-            .node()
-            .child_by_field_name("arguments")
-            .expect("error getting args");
+    /// Print the type of the expression
+    pub fn call_reveal_type(&mut self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
+        let fn_args_list = self.child(&cursor.node(), "arguments")?;
         let mut arg_list_cursor = fn_args_list.walk();
-        let arg_types: Vec<_> = fn_args_list
-            .named_children(&mut arg_list_cursor)
-            .map(|n| {
-                let arg = n.utf8_text(self.src.as_bytes()).expect("parse error");
-                if let Some(ty) = self.env.var_type(arg) {
+        let arg_nodes: Vec<Node> = fn_args_list.named_children(&mut arg_list_cursor).collect();
+        for n in &arg_nodes {
+            let arg = self.node_text(n)?;
+            match self.infer_type_for_node(n) {
+                Ok(ty) => {
                     let pos = cursor.node().start_position();
                     println!(
                         "[{}] {}:{}:{} {} -> {}",
@@ -275,17 +1108,17 @@ impl<'a> Checker<'a> {
                         arg,
                         ty
                     );
-                    Some(ty)
-                } else {
+                }
+                Err(err) => {
                     error!("No type for {}", arg);
-                    None
+                    self.report(err);
                 }
-            })
-            .collect();
+            }
+        }
         // print them all but its an error to have more then one positional arg
-        if arg_types.len() > 1 {
+        if arg_nodes.len() > 1 {
             return Err(CheckErr::new_from_node("To many arguments", &fn_args_list));
-        } else if arg_types.is_empty() {
+        } else if arg_nodes.is_empty() {
             return Err(CheckErr::new_from_node("No argument give", &fn_args_list));
         }
         Ok(())
@@ -294,147 +1127,325 @@ impl<'a> Checker<'a> {
     pub fn check_fn_call(&mut self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
         debug!("fn call {}", cursor.node());
         let fn_call_node = cursor.node();
-        let fn_name = cursor
-            .node()
-            .child_by_field_name("function")
-            .and_then(|n| n.utf8_text(self.src.as_bytes()).ok())
-            .expect("error getting fn name");
+        let fn_node = self.child(&fn_call_node, "function")?;
+        let fn_name = self.node_text(&fn_node)?;
 
         // special case for `reveal_type`
         if fn_name == "reveal_type" {
             return self.call_reveal_type(cursor);
         }
 
-        let _scope_guard = self.env.enter_scope(fn_name);
-        let fn_sig = self.env.var_type(fn_name);
-        let fn_args_list = cursor
-            .node()
-            .child_by_field_name("arguments")
-            .expect("error getting args");
-
-        if let Some(TypeVar::Function(_, params, _)) = fn_sig {
-            debug!("found fn sig {:?} p {}", params, fn_args_list);
-            let mut param_cursor = fn_args_list.walk();
-
-            // convert all of the ast nodes for args to types
-            let arg_types: Vec<(Node, Result<TypeVar, CheckErr>)> = fn_args_list
-                .named_children(&mut param_cursor)
-                .map(|n| {
-                    (
-                        n,
-                        self.infer_type_for_node(&n).ok_or_else(|| {
-                            CheckErr::new(
-                                "no type available",
-                                Place::from_ts_point("fnarg", n.start_position()),
-                                None,
-                            )
-                        }),
-                    )
-                })
-                .collect();
-
-            // check the same amount of args was used for the fn signature
-            if arg_types.len() != params.len() {
-                return Err(CheckErr::new(
-                    &format!(
-                        "Fn called with {} args expected {}",
-                        arg_types.len(),
-                        params.len()
-                    ),
-                    Place::from_ts_point("fncall", fn_call_node.start_position()),
-                    Some(Place::from_ts_point("fncall", fn_call_node.end_position())),
-                ));
-            }
-            // compare function params and args
-            for idx in 0..arg_types.len() {
-                if let Some((n, Ok(arg_ty))) = arg_types.get(idx) {
-                    let b = params.get(idx).unwrap();
-                    if !arg_ty.type_check(b) {
-                        self.errors.push(CheckErr::new(
-                            &format!(
-                                "Type mismatch calling fn `{}` Expected {} found {}",
-                                fn_name, b, arg_ty
-                            ),
-                            Place::from_ts_point("arg", n.start_position()),
-                            Some(Place::from_ts_point("arg", n.end_position())),
-                        ));
-                    }
-                } else if let Some((_, Err(e))) = arg_types.get(idx) {
-                    self.errors.push(e.clone());
+        // reports undefined names and missing attributes
+        let callee = self.infer_or_any(&fn_node);
+        let arg_nodes = self.call_arg_nodes(&fn_call_node)?;
+
+        // each member of a union has to accept the arguments
+        let mut checked = false;
+        for member in callee.members() {
+            match self.call_signature(&member) {
+                CallSig::Known(params, _) => {
+                    debug!("found fn sig {:?}", params);
+                    self.check_call_args(fn_name, &params, &arg_nodes, &fn_call_node);
+                    checked = true;
                 }
+                CallSig::Unknown(_) => {}
+                CallSig::NotCallable => self.report(CheckErr::new_from_node(
+                    &format!("'{}' of type {} is not callable", fn_name, member),
+                    &fn_node,
+                )),
             }
-        };
-
+        }
+        if !checked {
+            for arg in &arg_nodes {
+                self.infer_or_any(arg);
+            }
+        }
         Ok(())
     }
 
-    pub fn check_binop(&mut self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
-        let node = cursor.node();
-        let binop_place = Place::from_ts_point("binop", node.start_position());
+    /// How a value of type `callee` is called
+    /// Classes are called through `__init__` and instances through `__call__`
+    fn call_signature(&self, callee: &TypeVar) -> CallSig {
+        match callee {
+            TypeVar::Function(_, params, ret) => {
+                CallSig::Known(params.clone(), TypeVar::union(ret.clone()))
+            }
+            TypeVar::Class(cls) => match self.constructor_params(cls) {
+                Some(params) => CallSig::Known(params, self.instance_type(cls)),
+                None => CallSig::Unknown(self.instance_type(cls)),
+            },
+            TypeVar::Instance(cls) => match self.class_attr(cls, "__call__") {
+                Some(call) => self.call_signature(&self.bind_method(call, true)),
+                None => CallSig::NotCallable,
+            },
+            TypeVar::Any | TypeVar::Var(_) => CallSig::Unknown(TypeVar::Any),
+            // an empty union can't be called, it can't be a value either
+            TypeVar::Union(_) => CallSig::Unknown(TypeVar::Any),
+            _ => CallSig::NotCallable,
+        }
+    }
 
-        let arg1 = node.child_by_field_name("left").expect("error getting lhs");
-        let arg2 = node
-            .child_by_field_name("right")
-            .expect("error getting rhs");
+    /// Type of `value[index]`
+    fn subscript_type(&mut self, value: &TypeVar, index: &Node) -> Result<TypeVar, CheckErr> {
+        let is_slice = index.kind() == "slice";
+        let ty = match value {
+            TypeVar::List(_) | TypeVar::Tuple(_) if is_slice => value.clone(),
+            TypeVar::String() | TypeVar::Bytes() if is_slice => value.clone(),
+            TypeVar::List(elem) => *elem.clone(),
+            TypeVar::Dict(_, val) => *val.clone(),
+            TypeVar::String() => TypeVar::String(),
+            TypeVar::Bytes() => TypeVar::Integer(),
+            TypeVar::Tuple(elems) => {
+                // literal indexes pick the element, anything else could be any element
+                let literal = match index.kind() {
+                    "integer" => self.node_text(index)?.parse::<usize>().ok(),
+                    _ => None,
+                };
+                match literal {
+                    Some(i) => elems.get(i).cloned().ok_or_else(|| {
+                        CheckErr::new_from_node("Tuple index out of range", index)
+                    })?,
+                    None => TypeVar::union(elems.clone()),
+                }
+            }
+            _ => TypeVar::Any,
+        };
+        Ok(ty)
+    }
 
-        let a1_place = Place::from_ts_point("arg1", arg1.start_position());
-        let a1_type = self.infer_type_for_node(&arg1).expect("no type infered");
+    /// Argument nodes of a call
+    fn call_arg_nodes<'t>(&self, call: &Node<'t>) -> Result<Vec<Node<'t>>, CheckErr> {
+        let args = self.child(call, "arguments")?;
+        // `f(x for x in xs)` passes a generator without an argument list
+        Ok(match args.kind() {
+            "argument_list" => args
+                .named_children(&mut args.walk())
+                .filter(|n| n.kind() != "comment")
+                .collect(),
+            _ => vec![args],
+        })
+    }
 
-        let a2_place = Place::from_ts_point("arg2", arg2.start_position()).clone();
-        let a2_type = self.infer_type_for_node(&arg2).expect("no type infered");
+    /// Match the arguments of a call to the parameters they are passed to and check their types
+    /// Reports missing, duplicate and unknown arguments
+    /// Returns the type of each parameter that was matched with the type of its argument
+    fn check_call_args(
+        &mut self,
+        fn_name: &str,
+        params: &[Param],
+        args: &[Node],
+        call: &Node,
+    ) -> Vec<(TypeVar, TypeVar)> {
+        let mut bindings = Vec::new();
+        let mut filled = vec![false; params.len()];
+        // `*xs` and `**kw` arguments could fill any parameter
+        let mut star_args = false;
+        let mut star_kwargs = false;
+        let mut positional_count = 0;
 
-        let return_place = Place::from_ts_point("return", node.start_position());
-        let return_type = match (&a1_type, &a2_type) {
-            (TypeVar::Integer(a), TypeVar::Integer(b)) => TypeVar::Integer(a + b),
-            (TypeVar::String(), TypeVar::String()) => TypeVar::String(),
-            err => {
-                debug!("types not handled {:?}", err);
-                return Err(CheckErr::new(
-                    &format!("Invalid types {:?} for BinOp", err),
-                    binop_place,
-                    Some(Place::from_ts_point("binop", node.end_position())),
+        for arg in args {
+            let (param, value) = match arg.kind() {
+                "list_splat" => {
+                    star_args = true;
+                    self.infer_or_any(arg);
+                    continue;
+                }
+                "dictionary_splat" => {
+                    star_kwargs = true;
+                    self.infer_or_any(arg);
+                    continue;
+                }
+                "keyword_argument" => {
+                    let (Ok(name_node), Ok(value)) =
+                        (self.child(arg, "name"), self.child(arg, "value"))
+                    else {
+                        continue;
+                    };
+                    let name = self.node_text(&name_node).unwrap_or_default();
+                    let by_name = params.iter().position(|p| p.is_keyword() && p.name == name);
+                    let param = match by_name {
+                        Some(i) if filled[i] => {
+                            self.report(CheckErr::new_from_node(
+                                &format!(
+                                    "Fn `{}` got multiple values for argument '{}'",
+                                    fn_name, name
+                                ),
+                                arg,
+                            ));
+                            None
+                        }
+                        Some(i) => {
+                            filled[i] = true;
+                            Some(&params[i])
+                        }
+                        None => {
+                            let kwargs = params.iter().find(|p| p.kind == ParamKind::VarKeyword);
+                            let positional_only = params
+                                .iter()
+                                .any(|p| p.kind == ParamKind::PositionalOnly && p.name == name);
+                            let msg = if positional_only {
+                                "got positional-only argument passed as keyword"
+                            } else {
+                                "got an unexpected keyword argument"
+                            };
+                            if kwargs.is_none() {
+                                self.report(CheckErr::new_from_node(
+                                    &format!("Fn `{}` {} '{}'", fn_name, msg, name),
+                                    arg,
+                                ));
+                            }
+                            kwargs
+                        }
+                    };
+                    (param, value)
+                }
+                _ => {
+                    positional_count += 1;
+                    let next = (0..params.len()).find(|&i| params[i].is_positional() && !filled[i]);
+                    let param = match next {
+                        // positional args fill params in order, a `*xs` before could have taken it
+                        Some(i) if !star_args => {
+                            filled[i] = true;
+                            Some(&params[i])
+                        }
+                        _ => {
+                            let args_param =
+                                params.iter().find(|p| p.kind == ParamKind::VarPositional);
+                            if args_param.is_none() && !star_args {
+                                let max_positional =
+                                    params.iter().filter(|p| p.is_positional()).count();
+                                self.report(CheckErr::new_from_node(
+                                    &format!(
+                                        "Fn `{}` called with {} positional args expected at most {}",
+                                        fn_name, positional_count, max_positional
+                                    ),
+                                    arg,
+                                ));
+                            }
+                            args_param
+                        }
+                    };
+                    (param, *arg)
+                }
+            };
+
+            let arg_ty = self.infer_or_any(&value);
+            let Some(param) = param else {
+                continue;
+            };
+            if !Self::value_fits(&param.ty, &value, &arg_ty) {
+                self.report(CheckErr::new_from_node(
+                    &format!(
+                        "Type mismatch calling fn `{}` Expected {} found {}",
+                        fn_name, param.ty, arg_ty
+                    ),
+                    &value,
                 ));
             }
-        };
+            if arg_ty.contains_var() {
+                self.constrain(&param.ty, &arg_ty, &value);
+            }
+            bindings.push((param.ty.clone(), arg_ty));
+        }
 
-        let binop_type = TypeVar::Call(
-            binop_place.clone(),
-            vec![a1_type.clone(), a2_type.clone()],
-            vec![return_type.clone()],
-        );
+        let missing: Vec<&str> = params
+            .iter()
+            .zip(&filled)
+            .filter(|(p, filled)| {
+                let maybe_filled =
+                    (star_args && p.is_positional()) || (star_kwargs && p.is_keyword());
+                p.is_required() && !**filled && !maybe_filled
+            })
+            .map(|(p, _)| p.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            self.report(CheckErr::new_from_node(
+                &format!(
+                    "Fn `{}` missing arguments for {}",
+                    fn_name,
+                    missing
+                        .iter()
+                        .map(|name| format!("'{}'", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                call,
+            ));
+        }
+        bindings
+    }
 
-        self.env
-            .insert_binding(binop_place.clone(), binop_type.clone());
-        self.env.insert_binding(a1_place.clone(), a1_type.clone());
-        self.env.insert_binding(a2_place.clone(), a2_type);
-        self.env
-            .insert_binding(return_place.clone(), return_type.clone());
+    /// Check the operands of a binary operator support it
+    /// The result is also stored at the place of the operator
+    pub fn check_binop(&mut self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
+        let node = cursor.node();
+        let return_type = self.infer_binop(&node)?;
+        let return_place = Place::from_ts_point("return", node.start_position());
+        self.env.insert_binding(return_place, return_type);
         Ok(())
     }
 
+    /// `x += v` assigns `x` the result of the operator,
+    /// other targets and operands that don't support it keep their type
+    fn check_augmented_assignment(&mut self, node: &Node) {
+        match self.infer_type_for_node(node) {
+            Ok(result) => {
+                if let Some(lhs) = node.child_by_field_name("left") {
+                    self.bind_target(&lhs, &result);
+                }
+            }
+            Err(err) => self.report(err),
+        }
+    }
+
     pub fn check_assignment(&mut self, cursor: &mut TreeCursor) -> Result<(), CheckErr> {
         let node = cursor.node();
-        let lhs = node
-            .child_by_field_name("left")
-            .expect("No lhs in assignment");
-        let id = lhs
-            .utf8_text(self.src.as_bytes())
-            .expect("couldnt decode value");
-
-        let left_place = Place::from_ts_point(id, lhs.start_position());
-        let rhs = node
+        let lhs = self.child(&node, "left")?;
+        // annotation only assignments like `x: int` have no right hand side
+        let rhs_type = node
             .child_by_field_name("right")
-            .expect("No rhs in assignment");
-        let rhs_type = self.infer_type_for_node(&rhs).expect("couldnt infer rhs");
+            .map(|rhs| self.infer_or_any(&rhs));
+        if lhs.kind() == "attribute" {
+            return self.check_attribute_assignment(&node, &lhs, rhs_type);
+        }
+        if lhs.kind() == "subscript" {
+            return self.check_subscript_assignment(&node, &lhs, rhs_type);
+        }
+        if matches!(
+            lhs.kind(),
+            "pattern_list" | "tuple_pattern" | "list_pattern" | "parenthesized_expression"
+        ) {
+            // `a, b = t` unpacks the value the same way a `for` target does
+            self.bind_target(&lhs, &rhs_type.unwrap_or(TypeVar::Any));
+            return Ok(());
+        }
+        if lhs.kind() != "identifier" {
+            return Err(CheckErr::unsupported(
+                &format!("unsupported assignment target {}", lhs.kind()),
+                &lhs,
+            ));
+        }
+        let id = self.node_text(&lhs)?;
+        let left_place = Place::from_ts_point(id, lhs.start_position());
 
         if let Some(type_node) = node.child_by_field_name("type") {
-            let ty = TypeVar::from_type_str(type_node.utf8_text(self.src.as_bytes()).unwrap())
-                .expect("unable to get type");
+            let declared = match self.final_annotation(&type_node) {
+                // a bare `Final` keeps the exact type of the value
+                Some(None) => Ok(rhs_type.clone().unwrap_or(TypeVar::Any)),
+                Some(Some(inner)) => self.annotation_type(&inner),
+                None => self.annotation_type(&type_node),
+            };
+            let ty = declared.unwrap_or_else(|err| {
+                self.report(err);
+                TypeVar::Any
+            });
             // left hand side of assignment is always going to be what is written in the type
             self.env.insert_binding(left_place.clone(), ty.clone());
             self.env.insert_var(id, left_place.clone());
             debug!("Explicit def type {} {}", type_node, ty);
-            if !ty.type_check(&rhs_type) {
+            if let (Some(rhs_type), Some(rhs)) = (rhs_type, node.child_by_field_name("right"))
+                && !Self::value_fits(&ty, &rhs, &rhs_type)
+            {
                 return Err(CheckErr::new_from_node(
                     &format!(
                         "Mismatched types while assigning to '{}' expected {} found {}",
@@ -444,6 +1455,13 @@ impl<'a> Checker<'a> {
                 ));
             }
         } else {
+            // the variable could be reassigned, only the type of the value is kept
+            let rhs_type = match self.current_enum() {
+                Some(cls) if !id.starts_with('_') => {
+                    TypeVar::Literal(LiteralValue::Enum(cls, id.to_string()))
+                }
+                _ => rhs_type.map_or(TypeVar::Any, |ty| ty.widened()),
+            };
             debug!(
                 "assignment with infered type lhs {} -> {}",
                 left_place, rhs_type
@@ -454,51 +1472,139 @@ impl<'a> Checker<'a> {
         Ok(())
     }
 
-    pub fn print_errors(&self) {
-        if self.errors.is_empty() {
-            println!("✅ {}", "Type Checks Passed!".bright_green());
-            return;
+    /// Assignment to an item like `xs[0] = x` or `d[k] = v`
+    /// The key and value have to fit the container or the `__setitem__` of an instance
+    fn check_subscript_assignment(
+        &mut self,
+        node: &Node,
+        lhs: &Node,
+        rhs_type: Option<TypeVar>,
+    ) -> Result<(), CheckErr> {
+        let container = self.infer_or_any(&self.child(lhs, "value")?);
+        let index = self.child(lhs, "subscript")?;
+        if index.kind() == "slice" {
+            // slices are assigned any iterable
+            return Ok(());
         }
-        let heading = format!("{} Error(s) found:", self.errors.len()).bright_magenta();
-        println!("{}", heading);
-        for err in &self.errors {
-            let line = err.start_place.row;
-            let col = err.start_place.column;
-
-            // line needs +1 to account for zero index
+        let index_type = self.infer_or_any(&index);
+        let (key, item) = match &container {
+            TypeVar::List(elem) => (TypeVar::Integer(), *elem.clone()),
+            TypeVar::Dict(key, val) => (*key.clone(), *val.clone()),
+            // instances take the key and value as the arguments of their `__setitem__`
+            TypeVar::Instance(_) | TypeVar::Tuple(_) | TypeVar::String() | TypeVar::Bytes() => {
+                let setitem = self.method_of(&container, "__setitem__");
+                match setitem.map(|method| self.call_signature(&method)) {
+                    Some(CallSig::Known(params, _)) => {
+                        let mut args = params.iter().filter(|p| p.is_positional());
+                        match (args.next(), args.next()) {
+                            (Some(key), Some(item)) => (key.ty.clone(), item.ty.clone()),
+                            _ => return Ok(()),
+                        }
+                    }
+                    Some(_) => return Ok(()),
+                    None => {
+                        return Err(CheckErr::new_from_node(
+                            &format!("{} object does not support item assignment", container),
+                            lhs,
+                        ));
+                    }
+                }
+            }
+            _ => return Ok(()),
+        };
+        if !Self::value_fits(&key, &index, &index_type) {
+            return Err(CheckErr::new_from_node(
+                &format!(
+                    "Invalid index type {} for {}, expected {}",
+                    index_type, container, key
+                ),
+                &index,
+            ));
+        }
+        match (rhs_type, node.child_by_field_name("right")) {
+            (Some(rhs_type), Some(rhs)) if !Self::value_fits(&item, &rhs, &rhs_type) => {
+                Err(CheckErr::new_from_node(
+                    &format!(
+                        "Mismatched types while assigning to '{}' expected {} found {}",
+                        self.node_text(lhs)?,
+                        item,
+                        rhs_type
+                    ),
+                    node,
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn print_errors(&self) {
+        if self.errors.is_empty() {
+            println!("✅ {}", "Type Checks Passed!".bright_green());
+            return;
+        }
+        let heading = format!("{} Error(s) found:", self.errors.len()).bright_magenta();
+        println!("{}", heading);
+        for err in &self.errors {
+            // line needs +1 to account for zero index
             println!(
-                "[{}] {}:{}:{} {} ",
+                "[{}] {}:{}:{} [{}] {} ",
                 "Error".bright_red(),
                 self.file_name,
-                line + 1,
-                col,
+                err.start_place.row + 1,
+                err.start_place.column,
+                err.kind,
                 err.msg,
             );
-            // print context
-            let ctx_line_start = max(0, line as i64 - 2);
-            let prefix_len = err.start_place.row.to_string().len() + 1;
-            for l in ctx_line_start..(line + 1) as i64 {
-                let prefix = format!("{:1$} | ", l + 1, prefix_len).cyan();
+            self.print_context(err);
+            if let Some(note) = &err.note {
                 println!(
-                    "{}{}",
-                    prefix,
-                    self.src.lines().nth(l as usize).unwrap().cyan()
+                    "[{}] {}:{}:{} {} ",
+                    "Note".bright_blue(),
+                    self.file_name,
+                    note.start_place.row + 1,
+                    note.start_place.column,
+                    note.msg,
                 );
+                self.print_context(note);
             }
+        }
+    }
 
-            if let Some(end_place) = &err.end_place {
-                let num_carrots = end_place.column - col;
+    /// Print the source lines leading up to an error and underline it
+    fn print_context(&self, err: &CheckErr) {
+        let line = err.start_place.row;
+        let col = err.start_place.column;
+        let ctx_line_start = max(0, line as i64 - 2);
+        let prefix_len = err.start_place.row.to_string().len() + 1;
+        for l in ctx_line_start..(line + 1) as i64 {
+            let prefix = format!("{:1$} | ", l + 1, prefix_len).cyan();
+            println!(
+                "{}{}",
+                prefix,
+                self.src.lines().nth(l as usize).unwrap_or_default().cyan()
+            );
+        }
 
-                let prefix = format!("{} | ", " ".repeat(prefix_len)).cyan();
-                println!(
-                    "{}{}{}",
-                    prefix,
-                    " ".repeat(col),
-                    "^".repeat(num_carrots).bright_red()
-                )
+        if let Some(end_place) = &err.end_place {
+            // spans over multiple lines are underlined to the end of the first line
+            let num_carrots = if end_place.row == line {
+                end_place.column.saturating_sub(col)
             } else {
-                println!("{}{}", " ".repeat(col), "".red())
-            }
+                self.src
+                    .lines()
+                    .nth(line)
+                    .map_or(0, |l| l.len().saturating_sub(col))
+            };
+
+            let prefix = format!("{} | ", " ".repeat(prefix_len)).cyan();
+            println!(
+                "{}{}{}",
+                prefix,
+                " ".repeat(col),
+                "^".repeat(num_carrots).bright_red()
+            )
+        } else {
+            println!("{}{}", " ".repeat(col), "".red())
         }
     }
 }
@@ -507,6 +1613,13 @@ impl<'a> Checker<'a> {
 mod tests {
     use super::*;
 
+    fn check(src: &str) -> Checker<'_> {
+        let mut checker = Checker::new(src, "test.py");
+        let tree = crate::ast::parse(src).expect("Issue parsing tree");
+        checker.check_module(&mut tree.walk());
+        checker
+    }
+
     #[test]
     fn find_add_error() {
         let src = "c = 1 + \"goo\"";
@@ -518,4 +1631,1715 @@ mod tests {
 
         assert_eq!(checker.errors.len(), 1);
     }
+
+    #[test]
+    fn undefined_name_is_reported() {
+        let checker = check("x = y\nz = x + 1");
+
+        assert_eq!(checker.errors.len(), 1);
+        assert_eq!(checker.errors[0].msg, "name 'y' is not defined");
+        assert_eq!(checker.errors[0].kind, ErrKind::Name);
+        assert_eq!(checker.env.var_type("x"), Some(TypeVar::Any));
+    }
+
+    #[test]
+    fn imports_bind_names() {
+        let src = "\
+import os.path
+import json as j
+from collections import OrderedDict, abc as c
+os.getcwd()
+j.dumps(OrderedDict(), c)
+def f():
+    import sys
+    return j.loads(sys.argv)
+";
+        let checker = check(src);
+
+        assert!(checker.errors.is_empty());
+        assert_eq!(checker.env.var_type("os"), Some(TypeVar::Any));
+        assert_eq!(checker.env.var_type("j"), Some(TypeVar::Any));
+        assert_eq!(checker.env.var_type("json"), None);
+    }
+
+    #[test]
+    fn imported_bases() {
+        let src = "\
+from base import Base
+import abc
+try:
+    from fast import Parser
+except ImportError:
+    class Parser:
+        pass
+class A(Base):
+    pass
+class B(abc.ABC):
+    pass
+class C(Parser):
+    pass
+A().anything
+";
+        let checker = check(src);
+        assert!(checker.errors.is_empty(), "{:?}", checker.errors);
+    }
+
+    #[test]
+    fn reveal_type_of_expressions() {
+        let src = "\
+class P:
+    x: int
+p = P()
+n = 1
+reveal_type(n + 1)
+reveal_type(p.x)
+reveal_type(m)
+";
+        let checker = check(src);
+
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(msgs, vec!["name 'm' is not defined"]);
+    }
+
+    #[test]
+    fn params_visible_in_fn_body() {
+        let checker = check("def f(a: int, b):\n    c = a + b\n    return c\n");
+
+        assert!(checker.errors.is_empty());
+    }
+
+    #[test]
+    fn builtins_stub_is_valid() {
+        let env = Checker::builtins_env();
+
+        assert!(matches!(env.var_type("len"), Some(TypeVar::Function(..))));
+        assert!(check(BUILTINS_STUB).errors.is_empty());
+    }
+
+    #[test]
+    fn builtin_calls() {
+        let checker = check("n = len(\"abc\")\ns = str(n)\nlen(1)\nord(n)\n");
+
+        assert_eq!(checker.env.var_type("n"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("s"), Some(TypeVar::String()));
+        assert_eq!(checker.errors.len(), 1);
+        assert!(
+            checker.errors[0]
+                .msg
+                .contains("Type mismatch calling fn `ord`")
+        );
+
+        let src = "\
+a = abs(-1.5)
+t = sum([1]) + min(1, 2) + max(1, 2)
+for i, v in enumerate(zip([1], [2])):
+    pass
+print(__name__, type(t), open('p'), super())
+r = round(a, 2)
+if hasattr(t, 'x') and issubclass(type(t), int) and any(map(str, [1])):
+    setattr(t, 'y', getattr(t, 'x', NotImplemented))
+try:
+    next(iter(reversed([1])))
+    __import__('os')
+except (OSError, ImportError, KeyboardInterrupt):
+    pass
+";
+        let checker = check(src);
+        assert!(checker.errors.is_empty());
+        // an int without ndigits and a float with them
+        assert_eq!(checker.env.var_type("r"), Some(TypeVar::Any));
+    }
+
+    #[test]
+    fn primitive_literals() {
+        let src = "\
+f = 1.5
+i = 2
+b = True
+y = b\"abc\"
+c = 3j
+x: float = i
+n: int = b
+m: int = f
+s: str = y
+z = i + f
+w = b + b
+";
+        let checker = check(src);
+
+        assert_eq!(checker.env.var_type("f"), Some(TypeVar::Float()));
+        assert_eq!(checker.env.var_type("b"), Some(TypeVar::Bool()));
+        assert_eq!(checker.env.var_type("y"), Some(TypeVar::Bytes()));
+        assert_eq!(checker.env.var_type("c"), Some(TypeVar::Complex()));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Mismatched types while assigning to 'm' expected Integer() found Float()",
+                "Mismatched types while assigning to 's' expected String() found Bytes()",
+            ]
+        );
+    }
+
+    #[test]
+    fn container_literals() {
+        let src = "\
+a = [1, 2]
+b = [1, \"x\"]
+c = {\"k\": 1.5}
+d = (1, \"x\")
+e = {b\"x\"}
+f = []
+g: list[int] = a
+h: list[float] = a
+i: dict[str, float] = c
+j: tuple[int, str] = d
+k: list[str] = f
+l: list[float] = [1, 2]
+m = [x + 1 for x in a if x]
+n = {k: v * 2 for k, v in [(1, 'a')]}
+def takes(xs: list[float]) -> list[float]:
+    return [1]
+takes([1, 2])
+";
+        let checker = check(src);
+
+        let int = || Box::new(TypeVar::Integer());
+        assert_eq!(checker.env.var_type("a"), Some(TypeVar::List(int())));
+        assert_eq!(
+            checker.env.var_type("b"),
+            Some(TypeVar::List(Box::new(TypeVar::Union(vec![
+                TypeVar::Integer(),
+                TypeVar::String()
+            ]))))
+        );
+        assert_eq!(
+            checker.env.var_type("c"),
+            Some(TypeVar::Dict(
+                Box::new(TypeVar::String()),
+                Box::new(TypeVar::Float())
+            ))
+        );
+        assert_eq!(
+            checker.env.var_type("d"),
+            Some(TypeVar::Tuple(vec![TypeVar::Integer(), TypeVar::String()]))
+        );
+        assert_eq!(
+            checker.env.var_type("e"),
+            Some(TypeVar::Set(Box::new(TypeVar::Bytes())))
+        );
+        assert_eq!(checker.env.var_type("m"), Some(TypeVar::List(int())));
+        assert_eq!(
+            checker.env.var_type("n"),
+            Some(TypeVar::Dict(int(), Box::new(TypeVar::String())))
+        );
+        // lists are invariant so a list of ints isn't a list of floats,
+        // a new list literal only needs its elements to fit
+        assert_eq!(checker.errors.len(), 1);
+        assert!(checker.errors[0].msg.contains("assigning to 'h'"));
+    }
+
+    #[test]
+    fn annotation_expressions() {
+        let src = "\
+a: int | None = None
+b: Optional[str] = \"x\"
+c: typing.Union[int, str] = 1
+d: \"list[ int ]\" = [1]
+e: tuple[()] = ()
+f: Foo = 1
+g: int[str] = 1
+h: 3 = 1
+";
+        let checker = check(src);
+
+        let int_or_none = TypeVar::Union(vec![TypeVar::Integer(), TypeVar::None]);
+        assert_eq!(checker.env.var_type("a"), Some(int_or_none));
+        assert_eq!(
+            checker.env.var_type("b"),
+            Some(TypeVar::Union(vec![TypeVar::String(), TypeVar::None]))
+        );
+        assert_eq!(
+            checker.env.var_type("c"),
+            Some(TypeVar::Union(vec![TypeVar::Integer(), TypeVar::String()]))
+        );
+        assert_eq!(
+            checker.env.var_type("d"),
+            Some(TypeVar::List(Box::new(TypeVar::Integer())))
+        );
+        assert_eq!(checker.env.var_type("e"), Some(TypeVar::Tuple(vec![])));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "name 'Foo' is not defined",
+                "'int' doesn't take type parameters",
+                "invalid type expression '3'",
+            ]
+        );
+    }
+
+    #[test]
+    fn none_narrowing() {
+        let src = "\
+def g(n: int) -> int:
+    return n
+
+def f(x: Optional[int]) -> int:
+    if x is not None:
+        g(x)
+    else:
+        g(x)
+    if x is None:
+        return 0
+    return x
+";
+        let checker = check(src);
+
+        // only the call in the else branch sees `x` as None
+        assert_eq!(checker.errors.len(), 1);
+        assert_eq!(checker.errors[0].start_place.row, 7);
+    }
+
+    #[test]
+    fn narrowing_ends_with_branch() {
+        let src = "\
+def g(n: int) -> int:
+    return n
+
+def f(x: int | None, y: int | None):
+    if x is not None and y is not None:
+        g(x)
+        g(y)
+    elif x is not None:
+        g(x)
+        g(y)
+    g(x)
+";
+        let checker = check(src);
+
+        let rows: Vec<usize> = checker.errors.iter().map(|e| e.start_place.row).collect();
+        assert_eq!(rows, vec![9, 10]);
+    }
+
+    #[test]
+    fn isinstance_narrowing() {
+        let src = "\
+def takes_int(n: int) -> int:
+    return n
+
+def takes_str(s: str) -> str:
+    return s
+
+def f(x: int | str | None, y: float):
+    if isinstance(x, int):
+        takes_int(x)
+    elif isinstance(x, (str, bytes)):
+        takes_str(x)
+    else:
+        takes_str(x)
+    if not isinstance(x, str):
+        takes_int(x)
+    if isinstance(y, int):
+        takes_int(y)
+";
+        let checker = check(src);
+
+        // x is None in the else branch and could still be None when it isn't a str
+        let rows: Vec<usize> = checker.errors.iter().map(|e| e.start_place.row).collect();
+        assert_eq!(rows, vec![12, 14]);
+    }
+
+    #[test]
+    fn class_instances_and_attributes() {
+        let src = "\
+class Point:
+    def __init__(self, x: int, label: str):
+        self.x = x
+        self.label = label
+
+    def get_x(self) -> int:
+        return self.x
+
+    @staticmethod
+    def origin() -> \"Point\":
+        return Point(0, \"origin\")
+
+p = Point(1, \"a\")
+x = p.get_x()
+o = Point.origin()
+l = p.label
+Point(\"a\", \"b\")
+p.missing
+p.x = \"s\"
+";
+        let checker = check(src);
+
+        let point = match checker.env.var_type("Point") {
+            Some(TypeVar::Class(cls)) => cls,
+            other => panic!("Point should be a class, got {:?}", other),
+        };
+        assert_eq!(
+            checker.env.var_type("p"),
+            Some(TypeVar::Instance(point.clone()))
+        );
+        assert_eq!(checker.env.var_type("x"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("o"), Some(TypeVar::Instance(point)));
+        assert_eq!(checker.env.var_type("l"), Some(TypeVar::String()));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Type mismatch calling fn `Point` Expected Integer() found Literal['a']",
+                "'Point' object has no attribute 'missing'",
+                "Mismatched types while assigning to 'p.x' expected Integer() found Literal['s']",
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_assigned_in_any_method() {
+        let src = "\
+class Counter:
+    def bump(self) -> int:
+        self.count = self.count + 1
+        return self.count
+    def reset(self):
+        self.total: float = 0
+        self.count = 0
+    def __init__(self):
+        self.count = 0
+        self.note = 'n'
+c = Counter()
+n: int = c.count
+t: float = c.total
+s: int = c.note
+c.count = 2
+c.note = 'm'
+";
+        let checker = check(src);
+
+        // only `__init__` decides the type of `count`, every method can use it
+        // and constants assigned to attributes are widened
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec!["Mismatched types while assigning to 's' expected Integer() found String()"]
+        );
+    }
+
+    #[test]
+    fn attribute_placeholders_and_new() {
+        let src = "\
+class Conn:
+    def __init__(self) -> None:
+        self.sock = None
+        self.port: int = 'p'
+        self.retries = None
+
+    def send(self) -> None:
+        self.sock.send()
+        self.retries.count
+
+    def connect(self, sock: object) -> None:
+        self.sock = sock
+
+class Point:
+    def __new__(cls, x: int, y: int):
+        return object.__new__(cls)
+
+Point(1, 2)
+Point(1)
+";
+        let checker = check(src);
+        let rows: Vec<(usize, &str)> = checker
+            .errors
+            .iter()
+            .map(|e| (e.start_place.row, e.msg.as_str()))
+            .collect();
+        // `retries` is only ever `None`, the placeholder for `sock` becomes `Any`
+        assert_eq!(rows.len(), 3, "{:?}", rows);
+        assert_eq!(
+            rows[0],
+            (
+                3,
+                "Mismatched types while assigning to 'self.port' expected Integer() found Literal['p']"
+            )
+        );
+        assert_eq!(rows[1], (8, "'None' has no attribute 'count'"));
+        assert_eq!(rows[2].0, 18);
+    }
+
+    #[test]
+    fn class_inheritance() {
+        let src = "\
+class Animal:
+    def __init__(self, name: str):
+        self.name = name
+
+    def speak(self) -> str:
+        return self.name
+
+class Dog(Animal):
+    def fetch(self) -> int:
+        return 0
+
+def greet(a: Animal) -> str:
+    return a.speak()
+
+d = Dog(\"rex\")
+n = d.name
+s = d.speak()
+greet(d)
+a: Animal = d
+b: Dog = Animal(\"cat\")
+d.fly
+
+class A: pass
+class B(A, object): pass
+class C(object, A): pass
+
+class MyError(ValueError): pass
+e: Exception = MyError(\"bad\")
+";
+        let checker = check(src);
+
+        assert_eq!(checker.env.var_type("n"), Some(TypeVar::String()));
+        assert_eq!(checker.env.var_type("s"), Some(TypeVar::String()));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Mismatched types while assigning to 'b' expected Instance(Dog) found Instance(Animal)",
+                "'Dog' object has no attribute 'fly'",
+                "Cannot create a consistent method resolution order for 'C'",
+            ]
+        );
+    }
+
+    #[test]
+    fn opaque_constructors_and_builtin_members() {
+        let src = "\
+from models import Model
+
+class P(Model):
+    pass
+
+class Q(P):
+    def __init__(self, x: int):
+        self.x = x
+
+p = P(1, name='p')
+q = Q(1, 2)
+try:
+    pass
+except ValueError as err:
+    print(err.args, err.__class__)
+o = object.__new__(P)
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        // `Q` defines its own `__init__` so its arguments are still checked
+        assert_eq!(msgs.len(), 1, "{:?}", msgs);
+        assert!(msgs[0].contains("expected at most 1"), "{:?}", msgs);
+        assert!(matches!(
+            checker.env.var_type("p"),
+            Some(TypeVar::Instance(_))
+        ));
+    }
+
+    #[test]
+    fn override_compatibility() {
+        let src = "\
+class Base:
+    def f(self, x: int) -> float:
+        return 1.5
+
+    def g(self, x: int) -> str:
+        return \"\"
+
+    def h(self) -> str:
+        return \"\"
+
+    def k(self, x: int = 0, *, y: int) -> None:
+        pass
+
+class Ok(Base):
+    def f(self, x: float) -> int:
+        return 0
+
+    def k(self, x: int = 1, z: int = 2, **kw: int) -> None:
+        pass
+
+class Bad(Base):
+    def f(self, x: bool) -> float:
+        return 1.5
+
+    def g(self, x: int, y: int) -> str:
+        return \"\"
+
+    def h(self) -> int:
+        return 0
+
+    def k(self, x: int, *, y: int) -> None:
+        pass
+";
+        let checker = check(src);
+
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Signature of 'f' is incompatible with supertype 'Base', parameter 'x' expected Integer() found Bool()",
+                "Signature of 'g' is incompatible with supertype 'Base', added required parameter 'y'",
+                "Signature of 'h' is incompatible with supertype 'Base', return type expected String() found Integer()",
+                "Signature of 'k' is incompatible with supertype 'Base', parameter 'x' needs a default",
+            ]
+        );
+        // the note points at the overridden method
+        let note = checker.errors[0].note.as_ref().unwrap();
+        assert_eq!((note.start_place.row, note.start_place.column), (1, 4));
+    }
+
+    #[test]
+    fn overrides_of_inferred_returns() {
+        let src = "\
+class Shape:
+    def area(self):
+        raise NotImplementedError
+
+    def name(self) -> str:
+        return 'shape'
+
+    @property
+    def sides(self):
+        return 0
+
+class Square(Shape):
+    def area(self):
+        return 1.5
+
+    def name(self):
+        return 1
+
+    @property
+    def sides(self) -> str:
+        return 'four'
+";
+        let checker = check(src);
+        assert!(checker.errors.is_empty(), "{:?}", checker.errors);
+    }
+
+    #[test]
+    fn call_arguments() {
+        let src = "\
+def f(a: int, /, b: str, c: int = 1, *args: str, d: bool, e: int = 2, **kwargs: float):
+    return kwargs
+
+f(1, \"b\", d=True)
+f(1, \"b\", 2, \"x\", \"y\", d=False, z=1.5)
+f(1, b=\"b\", c=3, d=True, e=4)
+f(a=1, b=\"b\", d=True)
+f(1, \"b\", 3, 4, d=True, z=\"s\")
+f(1, \"b\", b=\"c\", d=True)
+f(1)
+
+def g(x: int, *, y: int = 0) -> int:
+    return x
+
+g(1, 2)
+g(1, w=2)
+g(*[1, 2])
+g(**{\"x\": 1})
+def bad(x: int = \"a\"):
+    pass
+k = f(1, \"b\", d=True)
+print(\"a\", 1, sep=\"\")
+print(end=1)
+len(obj=[1])
+";
+        let checker = check(src);
+
+        assert_eq!(
+            checker.env.var_type("k"),
+            Some(TypeVar::Dict(
+                Box::new(TypeVar::String()),
+                Box::new(TypeVar::Float())
+            ))
+        );
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Fn `f` missing arguments for 'a'",
+                "Type mismatch calling fn `f` Expected String() found Literal[4]",
+                "Type mismatch calling fn `f` Expected Float() found Literal['s']",
+                "Fn `f` got multiple values for argument 'b'",
+                "Fn `f` missing arguments for 'b', 'd'",
+                "Fn `g` called with 2 positional args expected at most 1",
+                "Fn `g` got an unexpected keyword argument 'w'",
+                "Default for parameter expected Integer() found Literal['a']",
+                "Type mismatch calling fn `print` Expected String() found Literal[1]",
+                "Fn `len` got positional-only argument passed as keyword 'obj'",
+                "Fn `len` missing arguments for 'obj'",
+            ]
+        );
+    }
+
+    #[test]
+    fn call_expressions() {
+        let src = "\
+class Adder:
+    def __init__(self, n: int):
+        self.n = n
+
+    def add(self, x: int) -> int:
+        return x
+
+    def __call__(self, x: int) -> str:
+        return \"\"
+
+def make() -> Adder:
+    return Adder(1)
+
+def twice(x: int) -> int:
+    return x
+
+a = Adder(1)
+r = a.add(2)
+a.add(\"s\")
+Adder.add(a, 1)
+c = a(1)
+a(\"s\")
+m = make().add(1)
+make()(\"s\")
+fns = [twice]
+t = fns[0](1)
+fns[0](\"s\")
+n = 1
+n(2)
+";
+        let checker = check(src);
+
+        assert_eq!(checker.env.var_type("r"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("c"), Some(TypeVar::String()));
+        assert_eq!(checker.env.var_type("m"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("t"), Some(TypeVar::Integer()));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Type mismatch calling fn `a.add` Expected Integer() found Literal['s']",
+                "Type mismatch calling fn `a` Expected Integer() found Literal['s']",
+                "Type mismatch calling fn `make()` Expected Integer() found Literal['s']",
+                "Type mismatch calling fn `fns[0]` Expected Integer() found Literal['s']",
+                "'n' of type Integer() is not callable",
+            ]
+        );
+        // calls no longer create scopes named after the callee
+        assert!(checker.env.scope_var_type("a.add", "x").is_none());
+    }
+
+    #[test]
+    fn binary_operators() {
+        let src = "\
+class Vec:
+    def __add__(self, other: \"Vec\") -> \"Vec\":
+        return self
+
+    def __rmul__(self, k: int) -> \"Vec\":
+        return self
+
+a = 1 / 2
+b = 7 // 2
+c = 2 * 1.5
+d = \"ab\" * 3
+e = 3 * \"ab\"
+f = [1] + [\"s\"]
+g = (1,) + (\"s\",)
+h = {1} | {\"s\"}
+i = True + True
+j = 1 << 2
+k = Vec() + Vec()
+l = 2 * Vec()
+m = int(\"3\") % 2
+n = \"%s\" % 1
+\"a\" - \"b\"
+Vec() * 2
+1 @ 2
+";
+        let checker = check(src);
+
+        let ty = |var: &str| checker.env.var_type(var).unwrap();
+        let str_or_int = || Box::new(TypeVar::union(vec![TypeVar::Integer(), TypeVar::String()]));
+        assert_eq!(ty("a"), TypeVar::Float());
+        assert_eq!(ty("b"), TypeVar::Integer());
+        assert_eq!(ty("c"), TypeVar::Float());
+        assert_eq!(ty("d"), TypeVar::String());
+        assert_eq!(ty("e"), TypeVar::String());
+        assert_eq!(ty("f"), TypeVar::List(str_or_int()));
+        assert_eq!(
+            ty("g"),
+            TypeVar::Tuple(vec![TypeVar::Integer(), TypeVar::String()])
+        );
+        assert_eq!(ty("h"), TypeVar::Set(str_or_int()));
+        assert_eq!(ty("i"), TypeVar::Integer());
+        assert_eq!(ty("j"), TypeVar::Integer());
+        assert!(matches!(ty("k"), TypeVar::Instance(cls) if cls.name() == "Vec"));
+        assert!(matches!(ty("l"), TypeVar::Instance(cls) if cls.name() == "Vec"));
+        assert_eq!(ty("m"), TypeVar::Integer());
+        assert_eq!(ty("n"), TypeVar::String());
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Unsupported operand types for - (Literal['a'] and Literal['b'])",
+                "Unsupported operand types for * (Instance(Vec) and Literal[2])",
+                "Unsupported operand types for @ (Literal[1] and Literal[2])",
+            ]
+        );
+    }
+
+    #[test]
+    fn augmented_assignments() {
+        let src = "\
+s: str = 'a'
+s += 'b'
+s += 1
+n = 1
+n += 2.5
+xs = [1]
+xs += (2,)
+class Acc:
+    def __init__(self) -> None:
+        self.total = 0
+    def add(self, v: int) -> None:
+        self.total += v
+        self.total += 'x'
+";
+        let checker = check(src);
+
+        assert_eq!(checker.env.var_type("s"), Some(TypeVar::String()));
+        assert_eq!(checker.env.var_type("n"), Some(TypeVar::Float()));
+        assert!(matches!(checker.env.var_type("xs"), Some(TypeVar::List(_))));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Unsupported operand types for += (String() and Literal[1])",
+                "Unsupported operand types for += (Integer() and Literal['x'])",
+            ]
+        );
+    }
+
+    #[test]
+    fn binop_results_are_values() {
+        let src = "\
+def f(a: int) -> int:
+    return a * 2
+
+def g(a: float) -> float:
+    return a + 1
+
+x = 1 + 2
+f(x)
+f(1 + 2 * 3)
+y = g(x) / 2
+f(\"a\" + \"b\")
+z = f(x) + 1.5
+";
+        let checker = check(src);
+
+        assert_eq!(checker.env.var_type("x"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("y"), Some(TypeVar::Float()));
+        assert_eq!(checker.env.var_type("z"), Some(TypeVar::Float()));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec!["Type mismatch calling fn `f` Expected Integer() found String()"]
+        );
+    }
+
+    #[test]
+    fn comparison_boolean_and_unary_operators() {
+        let src = "\
+class Money:
+    def __lt__(self, other: \"Money\") -> bool:
+        return True
+
+    def __neg__(self) -> \"Money\":
+        return self
+
+a = 1 < 2.5
+b = 1 < 2 <= 3
+c = \"a\" in \"abc\"
+d = 1 in [1, 2]
+e = not 1
+f = 1 or \"s\"
+g = -1.5
+h = ~True
+i = Money() > Money()
+j = -Money()
+k = None is None
+1 < \"a\"
+1 in \"abc\"
+-\"a\"
+1 in 2
++Money()
+";
+        let checker = check(src);
+
+        let ty = |var: &str| checker.env.var_type(var).unwrap();
+        assert_eq!(ty("a"), TypeVar::Bool());
+        assert_eq!(ty("b"), TypeVar::Bool());
+        assert_eq!(ty("c"), TypeVar::Bool());
+        assert_eq!(ty("d"), TypeVar::Bool());
+        assert_eq!(ty("e"), TypeVar::Bool());
+        assert_eq!(
+            ty("f"),
+            TypeVar::union(vec![TypeVar::Integer(), TypeVar::String()])
+        );
+        assert_eq!(ty("g"), TypeVar::Float());
+        assert_eq!(ty("h"), TypeVar::Integer());
+        assert_eq!(ty("i"), TypeVar::Bool());
+        assert!(matches!(ty("j"), TypeVar::Instance(cls) if cls.name() == "Money"));
+        assert_eq!(ty("k"), TypeVar::Bool());
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Unsupported operand types for < (Literal[1] and Literal['a'])",
+                "Unsupported operand types for in (Literal[1] and Literal['abc'])",
+                "Unsupported operand type for unary - (Literal['a'])",
+                "Unsupported operand types for in (Literal[1] and Literal[2])",
+                "Unsupported operand type for unary + (Instance(Money))",
+            ]
+        );
+    }
+
+    #[test]
+    fn return_types() {
+        let src = "\
+class Base: ...
+class Child(Base): ...
+
+def lit() -> int:
+    return 5
+def sub() -> Base:
+    return Child()
+def opt(x: int) -> int | None:
+    if x:
+        return x
+    return None
+def anything(x) -> str:
+    return x
+def wrong() -> str:
+    return 1
+def bare() -> int:
+    return
+def falls_off(x: int) -> int:
+    if x:
+        return x
+def branches(x: int) -> int:
+    if x:
+        return 1
+    elif x > 2:
+        raise ValueError()
+    else:
+        return 2
+def loops() -> int:
+    while True:
+        pass
+def breaks() -> int:
+    while True:
+        break
+def maybe(x: int) -> int | None:
+    if x:
+        return x
+def stub() -> int: ...
+def handled() -> int:
+    try:
+        return 1
+    except ValueError:
+        return 2
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Incompatible return type expected String() found Literal[1]",
+                "Incompatible return type expected Integer() found None",
+                "Missing return statement",
+                "Missing return statement",
+            ]
+        );
+        let lines: Vec<usize> = checker
+            .errors
+            .iter()
+            .map(|e| e.start_place.row + 1)
+            .collect();
+        assert_eq!(lines, vec![15, 17, 18, 31]);
+    }
+
+    #[test]
+    fn nested_function_returns() {
+        let src = "\
+def outer(x: int):
+    y = 'a'
+    def inner():
+        return y
+    class K:
+        def m(self):
+            return 1.5
+    f = lambda: None
+    return x
+def counter():
+    n = 0
+    def inc() -> int:
+        return n + 1
+    return inc
+def bad():
+    class C:
+        return 1
+add = lambda a, b=1: a + b
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(msgs, vec!["'return' outside function"]);
+        let returns = |name: &str| match checker.env.var_type(name) {
+            Some(TypeVar::Function(_, params, ret)) => (params.len(), ret),
+            other => panic!("{} is not a function {:?}", name, other),
+        };
+        assert_eq!(returns("outer"), (1, vec![TypeVar::Integer()]));
+        let (_, counter_ret) = returns("counter");
+        assert!(
+            matches!(&counter_ret[..], [TypeVar::Function(_, _, inner)] if inner == &vec![TypeVar::Integer()])
+        );
+        assert_eq!(returns("bad"), (0, vec![TypeVar::None]));
+        assert_eq!(returns("add"), (2, vec![TypeVar::Any]));
+    }
+
+    #[test]
+    fn closures_see_later_assignments() {
+        let src = "\
+def report():
+    return total + 1
+def outer():
+    def inner():
+        return count
+    count = 1
+    return inner()
+total = 0
+def missing():
+    return nowhere
+";
+        let checker = check(src);
+
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(msgs, vec!["name 'nowhere' is not defined"]);
+    }
+
+    #[test]
+    fn forward_references() {
+        let src = "\
+def is_even(n: int) -> bool:
+    return n == 0 or is_odd(n - 1)
+def is_odd(n: int) -> bool:
+    return not is_even(n)
+def make() -> Later:
+    return Later()
+def label() -> int:
+    return make().name
+class Later:
+    name: str
+    def twin(self) -> Later:
+        return self.other()
+    def other(self) -> Later:
+        return self
+def fact(n: int):
+    if n:
+        return n * fact(n - 1)
+    return 1
+def depth(n):
+    if n:
+        return depth(n - 1) + 1
+    return 0
+early = helper()
+def helper() -> int:
+    return 1
+late = helper()
+def outer():
+    inner()
+    def inner():
+        pass
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        // function bodies can use later definitions, statements running before them can't
+        assert_eq!(
+            msgs,
+            vec![
+                "Incompatible return type expected Integer() found String()",
+                "name 'helper' is not defined",
+                "local variable 'inner' referenced before assignment",
+            ]
+        );
+        let Some(TypeVar::Function(_, _, ret)) = checker.env.var_type("fact") else {
+            panic!("fact is a function");
+        };
+        // recursive calls don't make the return type Any
+        assert_eq!(TypeVar::union(ret), TypeVar::Integer());
+        // using the result of a recursive call isn't an error while its type settles
+        let Some(TypeVar::Function(_, _, ret)) = checker.env.var_type("depth") else {
+            panic!("depth is a function");
+        };
+        assert_eq!(TypeVar::union(ret), TypeVar::Integer());
+    }
+
+    #[test]
+    fn imported_enums() {
+        let src = "\
+from enum import Enum, IntEnum as Ints
+class Color(Enum):
+    RED = 1
+    GREEN = 2
+class Level(Ints):
+    LOW = 1
+c: Literal[Color.RED] = Color.RED
+d: Literal[Color.RED] = Color.GREEN
+low: Literal[Level.LOW] = Level.LOW
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Mismatched types while assigning to 'd' expected Literal[Color.RED] found Literal[Color.GREEN]"
+            ]
+        );
+    }
+
+    #[test]
+    fn item_assignment() {
+        let src = "\
+xs = [1, 2]
+xs[0] = 3
+xs[1] = 'a'
+xs['k'] = 1
+xs[0:1] = [4]
+d = {'a': 1}
+d['b'] = 2
+d[1] = 2
+t = (1, 2)
+t[0] = 1
+class Grid:
+    def __setitem__(self, pos: int, value: str) -> None: ...
+g = Grid()
+g[0] = 'x'
+g[0] = 1
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Mismatched types while assigning to 'xs[1]' expected Integer() found Literal['a']",
+                "Invalid index type Literal['k'] for List(Integer()), expected Integer()",
+                "Invalid index type Literal[1] for Dict(String(), Integer()), expected String()",
+                "Tuple(Integer(), Integer()) object does not support item assignment",
+                "Mismatched types while assigning to 'g[0]' expected String() found Literal[1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn match_statements() {
+        let src = "\
+from abc import ABC, abstractmethod
+def name(code: int) -> str:
+    match code:
+        case 1:
+            return 'one'
+        case [first, *rest] if first > 0:
+            return str(first)
+        case Point(x=px) as p:
+            return str(px)
+        case _:
+            return 'other'
+
+def partial(code: int) -> str:
+    match code:
+        case 1:
+            return 'one'
+
+def captured(cmd: list) -> int:
+    match cmd:
+        case {'k': v, **kw}:
+            n = 1
+        case other:
+            n = 2
+    return n
+
+class Shape(ABC):
+    @abstractmethod
+    def area(self) -> float:
+        pass
+
+def plain() -> int:
+    pass
+";
+        let checker = check(src);
+        let errs: Vec<(usize, &str)> = checker
+            .errors
+            .iter()
+            .map(|e| (e.start_place.row, e.msg.as_str()))
+            .collect();
+        assert_eq!(
+            errs,
+            vec![
+                (12, "Missing return statement"),
+                (30, "Missing return statement"),
+            ]
+        );
+    }
+
+    #[test]
+    fn literal_types() {
+        let src = "\
+class Color(Enum):
+    RED = 1
+    GREEN = 2
+
+x = 1
+y: Final = 1
+z: Final[int] = 2
+mode: Literal['r', 'w'] = 'r'
+bad: Literal['r', 'w'] = 'x'
+neg: Literal[-1] = -1
+flag: Literal[True] = False
+c: Literal[Color.RED] = Color.RED
+d: Literal[Color.RED] = Color.GREEN
+e = Color.RED
+m = mode
+nums = [1, 2]
+def f(n: Literal[1, 2]) -> Literal[1, 2]:
+    return n
+f(x)
+f(2)
+big = 999999999999999999999999999999999999999999
+lit: Literal[1.5] = 1
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Mismatched types while assigning to 'bad' expected Union(Literal['r'], Literal['w']) found Literal['x']",
+                "Mismatched types while assigning to 'flag' expected Literal[True] found Literal[False]",
+                "Mismatched types while assigning to 'd' expected Literal[Color.RED] found Literal[Color.GREEN]",
+                "Type mismatch calling fn `f` Expected Union(Literal[1], Literal[2]) found Integer()",
+                "invalid Literal parameter '1.5'",
+            ]
+        );
+        let lit_int = |v| Some(TypeVar::Literal(LiteralValue::Int(v)));
+        // non final variables only keep the type of the value
+        assert_eq!(checker.env.var_type("x"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("y"), lit_int(1));
+        assert_eq!(checker.env.var_type("z"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("neg"), lit_int(-1));
+        assert_eq!(checker.env.var_type("m"), Some(TypeVar::String()));
+        assert_eq!(
+            checker.env.var_type("nums"),
+            Some(TypeVar::List(Box::new(TypeVar::Integer())))
+        );
+        assert_eq!(checker.env.var_type("big"), Some(TypeVar::Integer()));
+        let Some(TypeVar::Instance(color)) = checker.env.var_type("e") else {
+            panic!("enum members are instances of the enum");
+        };
+        assert_eq!(color.name(), "Color");
+    }
+
+    #[test]
+    fn infer_unannotated_params() {
+        let src = "\
+def add(a, b):
+    return a + b + 1
+
+def greet(name):
+    return \"hi \" + name
+
+def ident(x):
+    return x
+
+def scale(x, k=2.5):
+    return x * k
+
+def conflict(x):
+    ord(x)
+    abs(x)
+
+s = greet(\"bob\")
+i = ident(3)
+t = ident(\"s\")
+f = scale(1)
+add(1, 2)
+add(\"a\", 2)
+greet(1)
+
+def rep(n):
+    return \"ab\" * n
+
+def half(x):
+    return x / 2
+
+r = rep(3)
+h = half(1.5)
+";
+        let mut checker = Checker::new(src, "test.py").with_param_inference(true);
+        let tree = crate::ast::parse(src).expect("Issue parsing tree");
+        checker.check_module(&mut tree.walk());
+
+        let ty = |var: &str| checker.env.var_type(var).unwrap();
+        assert_eq!(ty("s"), TypeVar::String());
+        assert_eq!(ty("i"), TypeVar::Integer());
+        assert_eq!(ty("t"), TypeVar::String());
+        assert_eq!(ty("f"), TypeVar::Float());
+        let TypeVar::Function(_, params, ret) = ty("add") else {
+            panic!("add should be a function");
+        };
+        assert_eq!(params[1].ty, TypeVar::Integer());
+        assert_eq!(ret, vec![TypeVar::Integer()]);
+        // operators that don't take two values of the same type leave the parameter unknown
+        let TypeVar::Function(_, params, _) = ty("half") else {
+            panic!("half should be a function");
+        };
+        assert!(matches!(params[0].ty, TypeVar::Var(_)));
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "Conflicting uses of parameter 'x', expected Float() found String()",
+                "Type mismatch calling fn `add` Expected Integer() found Literal['a']",
+                "Type mismatch calling fn `greet` Expected String() found Literal[1]",
+            ]
+        );
+
+        // without inference unannotated params accept anything
+        let checker = check(src);
+        assert!(checker.errors.is_empty());
+        assert_eq!(checker.env.var_type("i"), Some(TypeVar::Any));
+    }
+
+    #[test]
+    fn unsupported_constructs_dont_panic() {
+        let src = "\
+a, b = 1, 2
+[a][0] = b
+t: tuple[int, ...] = (1,)
+c: int
+d = 99999999999999999999999999
+def f(x: 1, *args, y=1, **kwargs) -> 2:
+    return x
+e = 1 + a
+";
+        let checker = check(src);
+
+        let kinds: Vec<ErrKind> = checker.errors.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ErrKind::Unsupported, ErrKind::Type, ErrKind::Type]
+        );
+        assert_eq!(checker.env.var_type("b"), Some(TypeVar::Integer()));
+        assert_eq!(checker.env.var_type("c"), Some(TypeVar::Integer()));
+    }
+
+    #[test]
+    fn flow_sensitive_types() {
+        let src = "\
+def g(n: int) -> int:
+    return n
+
+def cond() -> bool:
+    return True
+
+if cond():
+    a = 1
+else:
+    a = 'a'
+g(a)
+b = 'b'
+b = 2
+g(b)
+c = None
+while cond():
+    c = 1
+d = 1
+try:
+    d = 'd'
+except ValueError:
+    pass
+for e in [1]:
+    if cond():
+        break
+    f = 1
+else:
+    f = 2
+";
+        let checker = check(src);
+        let rows: Vec<usize> = checker.errors.iter().map(|e| e.start_place.row).collect();
+        // only the call with `a` that may be a string fails, `b` was reassigned
+        assert_eq!(rows, vec![10]);
+        let union = |tys: Vec<TypeVar>| Some(TypeVar::union(tys));
+        assert_eq!(
+            checker.env.var_type("a"),
+            union(vec![TypeVar::Integer(), TypeVar::String()])
+        );
+        assert_eq!(checker.env.var_type("b"), Some(TypeVar::Integer()));
+        assert_eq!(
+            checker.env.var_type("c"),
+            union(vec![TypeVar::None, TypeVar::Integer()])
+        );
+        assert_eq!(
+            checker.env.var_type("d"),
+            union(vec![TypeVar::String(), TypeVar::Integer()])
+        );
+        assert_eq!(checker.env.var_type("f"), Some(TypeVar::Integer()));
+    }
+
+    #[test]
+    fn except_and_walrus_targets() {
+        let src = "\
+class AppError(Exception):
+    code: int
+
+try:
+    pass
+except AppError as err:
+    code = err.code
+except (KeyError, ValueError) as either:
+    pass
+if (n := len('abc')) > 2:
+    pass
+
+def run() -> int:
+    try:
+        return 1
+    except KeyError as e:
+        raise ValueError(e)
+    finally:
+        print(1 + 'a')
+";
+        let checker = check(src);
+        let rows: Vec<usize> = checker.errors.iter().map(|e| e.start_place.row).collect();
+        // a `finally` after a body that always returns is still checked
+        assert_eq!(rows, vec![18], "{:?}", checker.errors);
+        let var = |name| checker.env.var_type(name);
+        assert_eq!(var("code"), Some(TypeVar::Integer()));
+        assert_eq!(var("n"), Some(TypeVar::Integer()));
+        assert!(matches!(var("either"), Some(TypeVar::Union(members)) if members.len() == 2));
+    }
+
+    #[test]
+    fn try_handlers_see_every_statement() {
+        let src = "\
+def g(n: int) -> int:
+    return n
+
+def cond() -> bool:
+    return True
+
+h = 1
+try:
+    h = 'h'
+    if cond():
+        h = None
+    h = 2
+except ValueError:
+    g(h)
+try:
+    fresh = 1
+    fresh = g(fresh)
+except ValueError:
+    print(fresh)
+";
+        let checker = check(src);
+        let errs: Vec<(usize, &str)> = checker
+            .errors
+            .iter()
+            .map(|e| (e.start_place.row, e.msg.as_str()))
+            .collect();
+        // the handler sees `h` as left by any statement of the body, not only the last
+        assert_eq!(errs.len(), 2, "{:?}", errs);
+        assert_eq!(
+            errs[0],
+            (
+                13,
+                "Type mismatch calling fn `g` Expected Integer() found Union(Integer(), String(), None)"
+            )
+        );
+        assert_eq!(errs[1], (18, "name 'fresh' is possibly unbound"));
+    }
+
+    #[test]
+    fn loop_types_settle() {
+        let src = "\
+def takes_int(x: int) -> None:
+    pass
+
+def cond() -> bool:
+    return True
+
+a = 1
+b = 1
+c = 1
+d = 1
+e = 1
+f = 1
+g = 1
+while cond():
+    takes_int(g)
+    g = f
+    f = e
+    e = d
+    d = c
+    c = b
+    b = a
+    a = 'a'
+nest = [1]
+while cond():
+    nest = [nest]
+";
+        let checker = check(src);
+
+        // the str reaches `g` after more passes than a type may change at a block
+        let msgs: Vec<(usize, &str)> = checker
+            .errors
+            .iter()
+            .map(|e| (e.start_place.row, e.msg.as_str()))
+            .collect();
+        assert_eq!(
+            msgs,
+            vec![(
+                14,
+                "Type mismatch calling fn `takes_int` Expected Integer() found Union(Integer(), String())"
+            )]
+        );
+        // a type that never settles becomes Any
+        assert_eq!(checker.env.var_type("nest"), Some(TypeVar::Any));
+    }
+
+    #[test]
+    fn outer_names_are_never_unbound() {
+        let src = "\
+def cond() -> bool:
+    return True
+
+def g(n: int) -> int:
+    return n
+
+count = 0
+limit: int | None = None
+def bump():
+    global count
+    if cond():
+        count = 1
+    print(count)
+def outer():
+    total = 0
+    def inner():
+        nonlocal total
+        if cond():
+            total = 1
+        return total
+    return inner
+def check():
+    if limit is not None and cond():
+        g(limit)
+    g(limit)
+";
+        let checker = check(src);
+        let errs: Vec<(usize, &str)> = checker
+            .errors
+            .iter()
+            .map(|e| (e.start_place.row, e.msg.as_str()))
+            .collect();
+        // `limit` is only narrowed along one path, after the `if` it could still be `None`
+        assert_eq!(errs.len(), 1, "{:?}", errs);
+        assert_eq!(errs[0].0, 24);
+        assert!(errs[0].1.contains("None"), "{:?}", errs);
+    }
+
+    #[test]
+    fn unbound_names() {
+        let src = "\
+def cond() -> bool:
+    return True
+
+if cond():
+    a = 1
+print(a)
+try:
+    b = 1
+except ValueError:
+    print(b)
+count = 0
+def bump():
+    print(count)
+    count = 1
+def total():
+    global count
+    count = count + 1
+def loop():
+    for i in [1]:
+        if i:
+            print(last)
+        last = i
+    while True:
+        done = 1
+        break
+    print(done)
+    print(i)
+";
+        let checker = check(src);
+        let errs: Vec<(usize, &str)> = checker
+            .errors
+            .iter()
+            .map(|e| (e.start_place.row, e.msg.as_str()))
+            .collect();
+        assert_eq!(
+            errs,
+            vec![
+                (5, "name 'a' is possibly unbound"),
+                (9, "name 'b' is possibly unbound"),
+                (12, "local variable 'count' referenced before assignment"),
+                (20, "name 'last' is possibly unbound"),
+                (26, "name 'i' is possibly unbound"),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_loop_targets() {
+        let src = "\
+class Countdown:
+    def __iter__(self) -> Countdown:
+        return self
+    def __next__(self) -> float:
+        return 1.0
+
+for a in [1, 2]:
+    pass
+for b in range(3):
+    pass
+for c, d in [(1, 'x')]:
+    pass
+for e in 'abc':
+    pass
+for f in Countdown():
+    pass
+for g in 5:
+    pass
+total = None
+for h in {'k': 1}:
+    total = 1
+for k, v in {'k': 1}.items():
+    pass
+";
+        let checker = check(src);
+        let msgs: Vec<&str> = checker.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(msgs, vec!["Literal[5] object is not iterable"]);
+        let var = |name| checker.env.var_type(name);
+        assert_eq!(var("a"), Some(TypeVar::Integer()));
+        assert_eq!(var("b"), Some(TypeVar::Integer()));
+        assert_eq!(var("c"), Some(TypeVar::Integer()));
+        assert_eq!(var("d"), Some(TypeVar::String()));
+        assert_eq!(var("e"), Some(TypeVar::String()));
+        assert_eq!(var("f"), Some(TypeVar::Float()));
+        assert_eq!(var("g"), Some(TypeVar::Any));
+        assert_eq!(var("h"), Some(TypeVar::String()));
+        // the loop may not run at all
+        assert_eq!(
+            var("total"),
+            Some(TypeVar::union(vec![TypeVar::None, TypeVar::Integer()]))
+        );
+        assert_eq!(var("k"), Some(TypeVar::String()));
+        assert_eq!(var("v"), Some(TypeVar::Integer()));
+    }
+
+    #[test]
+    fn generators_and_unknown_iterators() {
+        let src = "\
+from typing import Any
+class Bag:
+    def __iter__(self) -> Any:
+        return iter([1])
+class Gen:
+    def __iter__(self):
+        yield 1
+def gen():
+    yield 1
+    return
+for x in Bag():
+    pass
+for y in Gen():
+    pass
+for z in gen():
+    pass
+";
+        let checker = check(src);
+        assert!(checker.errors.is_empty(), "{:?}", checker.errors);
+        assert_eq!(checker.env.var_type("z"), Some(TypeVar::Any));
+    }
+
+    #[test]
+    fn unpacking_assignments() {
+        let src = "\
+ages = {'ann': 1}
+(a, s), f = (1, 'x'), 2.0
+[first, second] = [1, 2]
+names = list(ages.keys())
+for age in ages.values():
+    pass
+def swap(p: int, q: str) -> str:
+    p, q = q, p
+    return p
+";
+        let checker = check(src);
+        assert!(checker.errors.is_empty(), "{:?}", checker.errors);
+        let var = |name| checker.env.var_type(name);
+        assert_eq!(var("a"), Some(TypeVar::Integer()));
+        assert_eq!(var("s"), Some(TypeVar::String()));
+        assert_eq!(var("f"), Some(TypeVar::Float()));
+        assert_eq!(var("first"), Some(TypeVar::Integer()));
+        assert_eq!(var("second"), Some(TypeVar::Integer()));
+        assert_eq!(var("age"), Some(TypeVar::Integer()));
+    }
+
+    #[test]
+    fn with_targets() {
+        let src = "\
+class Lock:
+    def __enter__(self) -> int:
+        return 1
+
+with open('p') as fh, Lock() as n:
+    pass
+with open('p') as (p, q):
+    pass
+with Lock():
+    pass
+
+def read(path: str) -> str:
+    with open(path) as f:
+        return f.read()
+";
+        let checker = check(src);
+        assert!(checker.errors.is_empty(), "{:?}", checker.errors);
+        let var = |name| checker.env.var_type(name);
+        assert_eq!(var("fh"), Some(TypeVar::Any));
+        assert_eq!(var("n"), Some(TypeVar::Integer()));
+        assert_eq!(var("p"), Some(TypeVar::Any));
+        assert_eq!(var("q"), Some(TypeVar::Any));
+    }
 }
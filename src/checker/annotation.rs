@@ -0,0 +1,289 @@
+use crate::{
+    checker::{CheckErr, Checker, parse_int},
+    type_var::{LiteralValue, TypeVar},
+};
+use log::debug;
+use tree_sitter::Node;
+
+/// Names from `typing` that are valid types but can't be checked yet
+const UNSUPPORTED_TYPING_NAMES: &[&str] = &[
+    "Callable",
+    "Iterable",
+    "Iterator",
+    "Sequence",
+    "Mapping",
+    "Generator",
+    "Type",
+    "Final",
+    "ClassVar",
+];
+
+impl<'a> Checker<'a> {
+    /// Convert the `type` node of an annotation to a TypeVar
+    pub fn annotation_type(&self, type_node: &Node) -> Result<TypeVar, CheckErr> {
+        self.eval_type_expr(type_node)
+    }
+
+    /// Evaluate an expression in an annotation as a type
+    /// The tree is walked structurally so `Optional[int]`, `int | None` and `"int"` all work
+    fn eval_type_expr(&self, node: &Node) -> Result<TypeVar, CheckErr> {
+        debug!("type expr {}", node);
+        match node.kind() {
+            "type" | "parenthesized_expression" | "expression_statement" => {
+                match node.named_child(0) {
+                    Some(inner) => self.eval_type_expr(&inner),
+                    None => Err(self.invalid_type_expr(node)),
+                }
+            }
+            "none" => Ok(TypeVar::None),
+            "identifier" | "attribute" => {
+                let name = self.type_name(node)?;
+                let is_generic = TypeVar::is_generic_name(name) || name == "Literal";
+                if is_generic && TypeVar::from_type_str(name).is_none() {
+                    return Err(CheckErr::new_from_node(
+                        &format!("'{}' needs type parameters", name),
+                        node,
+                    ));
+                }
+                if let Some(ty) = TypeVar::from_type_str(name) {
+                    return Ok(ty);
+                }
+                // classes used as types mean instances of the class
+                match self.env.var_type(name) {
+                    Some(TypeVar::Class(cls)) if node.kind() == "identifier" => {
+                        Ok(TypeVar::Instance(cls))
+                    }
+                    _ => Err(self.unknown_type(name, node)),
+                }
+            }
+            // `list[int]`, the name is followed by a `type_parameter` node holding the params
+            "generic_type" => {
+                let name_node = node
+                    .named_child(0)
+                    .ok_or_else(|| self.invalid_type_expr(node))?;
+                let name = self.type_name(&name_node)?;
+                let param_nodes: Vec<Node> = node
+                    .named_child(1)
+                    .map(|params| params.named_children(&mut params.walk()).collect())
+                    .unwrap_or_default();
+                self.eval_generic(name, &param_nodes, node)
+            }
+            // `typing.List[int]` is parsed as a regular subscript expression
+            "subscript" => {
+                let name = self.type_name(&self.child(node, "value")?)?;
+                let param_nodes: Vec<Node> = node
+                    .children_by_field_name("subscript", &mut node.walk())
+                    .collect();
+                self.eval_generic(name, &param_nodes, node)
+            }
+            "binary_operator" | "union_type" => {
+                if let Some(op) = node.child_by_field_name("operator")
+                    && self.node_text(&op)? != "|"
+                {
+                    return Err(self.invalid_type_expr(node));
+                }
+                let members: Result<Vec<TypeVar>, CheckErr> = node
+                    .named_children(&mut node.walk())
+                    .map(|n| self.eval_type_expr(&n))
+                    .collect();
+                Ok(TypeVar::union(members?))
+            }
+            "string" => self.eval_forward_ref(node),
+            "ellipsis" => Err(CheckErr::unsupported(
+                "variable length tuples are not supported",
+                node,
+            )),
+            _ => Err(self.invalid_type_expr(node)),
+        }
+    }
+
+    /// Name of a type, `typing.` prefixes are dropped
+    fn type_name(&self, node: &Node) -> Result<&'a str, CheckErr> {
+        match node.kind() {
+            "identifier" => self.node_text(node),
+            "attribute" => {
+                let module = self.node_text(&self.child(node, "object")?)?;
+                let attr = self.node_text(&self.child(node, "attribute")?)?;
+                if module == "typing" {
+                    Ok(attr)
+                } else {
+                    Err(CheckErr::unsupported(
+                        &format!("unsupported type {}.{}", module, attr),
+                        node,
+                    ))
+                }
+            }
+            _ => Err(self.invalid_type_expr(node)),
+        }
+    }
+
+    /// `Final` or `Final[T]` annotation of an assignment
+    /// The inner node is `None` for a bare `Final`, the type then comes from the value
+    pub fn final_annotation<'t>(&self, type_node: &Node<'t>) -> Option<Option<Node<'t>>> {
+        let inner = type_node.named_child(0)?;
+        let (name_node, param) = match inner.kind() {
+            "identifier" | "attribute" => (inner, None),
+            "generic_type" => (
+                inner.named_child(0)?,
+                inner
+                    .named_child(1)
+                    .and_then(|params| params.named_child(0)),
+            ),
+            "subscript" => (
+                inner.child_by_field_name("value")?,
+                inner.child_by_field_name("subscript"),
+            ),
+            _ => return None,
+        };
+        (self.type_name(&name_node).ok()? == "Final").then_some(param)
+    }
+
+    fn eval_generic(
+        &self,
+        name: &str,
+        param_nodes: &[Node],
+        node: &Node,
+    ) -> Result<TypeVar, CheckErr> {
+        if name == "Literal" {
+            return self.eval_literal(param_nodes, node);
+        }
+        if !TypeVar::is_generic_name(name) {
+            return Err(if TypeVar::from_type_str(name).is_some() {
+                CheckErr::new_from_node(&format!("'{}' doesn't take type parameters", name), node)
+            } else {
+                self.unknown_type(name, node)
+            });
+        }
+        // `tuple[()]` is the empty tuple
+        let is_empty_tuple = |n: &Node| {
+            let inner = if n.kind() == "type" {
+                n.named_child(0)
+            } else {
+                Some(*n)
+            };
+            inner.is_some_and(|i| i.kind() == "tuple" && i.named_child_count() == 0)
+        };
+        let params: Vec<TypeVar> = match param_nodes {
+            [only] if is_empty_tuple(only) => vec![],
+            _ => param_nodes
+                .iter()
+                .filter(|n| n.kind() != "comment")
+                .map(|n| self.eval_type_expr(n))
+                .collect::<Result<_, _>>()?,
+        };
+        TypeVar::from_generic(name, &params).ok_or_else(|| {
+            CheckErr::new_from_node(&format!("invalid type parameters for '{}'", name), node)
+        })
+    }
+
+    /// `Literal[1, "a", Color.RED]`, a union of the constant values
+    fn eval_literal(&self, param_nodes: &[Node], node: &Node) -> Result<TypeVar, CheckErr> {
+        let values: Vec<TypeVar> = param_nodes
+            .iter()
+            .filter(|n| n.kind() != "comment")
+            .map(|n| self.literal_param(n))
+            .collect::<Result<_, _>>()?;
+        if values.is_empty() {
+            return Err(CheckErr::new_from_node(
+                "invalid type parameters for 'Literal'",
+                node,
+            ));
+        }
+        Ok(TypeVar::union(values))
+    }
+
+    fn literal_param(&self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let text = self.node_text(node)?;
+        let value = match node.kind() {
+            "type" | "parenthesized_expression" => match node.named_child(0) {
+                Some(inner) => return self.literal_param(&inner),
+                None => None,
+            },
+            "none" => return Ok(TypeVar::None),
+            // nested literals are flattened, `Literal[Literal[1], 2]`
+            "generic_type" | "subscript" => return self.eval_type_expr(node),
+            "integer" => parse_int(text).map(LiteralValue::Int),
+            "unary_operator" => match (
+                self.node_text(&self.child(node, "operator")?)?,
+                node.child_by_field_name("argument"),
+            ) {
+                ("-", Some(arg)) if arg.kind() == "integer" => {
+                    parse_int(self.node_text(&arg)?).map(|v| LiteralValue::Int(-v))
+                }
+                _ => None,
+            },
+            "true" => Some(LiteralValue::Bool(true)),
+            "false" => Some(LiteralValue::Bool(false)),
+            "string"
+                if !text
+                    .split(['"', '\''])
+                    .next()
+                    .unwrap_or_default()
+                    .contains(['b', 'B']) =>
+            {
+                self.string_value(node).map(LiteralValue::Str)
+            }
+            "attribute" => return self.enum_member(node),
+            _ => None,
+        };
+        value.map(TypeVar::Literal).ok_or_else(|| {
+            CheckErr::new_from_node(&format!("invalid Literal parameter '{}'", text), node)
+        })
+    }
+
+    /// Enum member used as a literal, eg. `Color.RED`
+    fn enum_member(&self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let obj = self.node_text(&self.child(node, "object")?)?;
+        let attr = self.node_text(&self.child(node, "attribute")?)?;
+        let member = match self.env.var_type(obj) {
+            Some(TypeVar::Class(cls)) => self.class_attr(&cls, attr),
+            _ => None,
+        };
+        match member {
+            Some(ty @ TypeVar::Literal(LiteralValue::Enum(..))) => Ok(ty),
+            _ => Err(CheckErr::new_from_node(
+                &format!("'{}.{}' is not an enum member", obj, attr),
+                node,
+            )),
+        }
+    }
+
+    /// String annotations like `"int"` are parsed again and evaluated as a type
+    fn eval_forward_ref(&self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let contents: Vec<Node> = node
+            .named_children(&mut node.walk())
+            .filter(|n| n.kind() != "string_start" && n.kind() != "string_end")
+            .collect();
+        let [content] = contents.as_slice() else {
+            return Err(self.invalid_type_expr(node));
+        };
+        if content.kind() != "string_content" {
+            return Err(self.invalid_type_expr(node));
+        }
+        let tree = crate::ast::parse_range(self.src, content.range())
+            .ok_or_else(|| self.invalid_type_expr(node))?;
+        let root = tree.root_node();
+        match root.named_child(0) {
+            Some(expr) if root.named_child_count() == 1 && !root.has_error() => {
+                self.eval_type_expr(&expr)
+            }
+            _ => Err(self.invalid_type_expr(node)),
+        }
+    }
+
+    fn invalid_type_expr(&self, node: &Node) -> CheckErr {
+        let text = self.node_text(node).unwrap_or_default();
+        CheckErr::new_from_node(&format!("invalid type expression '{}'", text), node)
+    }
+
+    /// Error for a name that isn't a known type
+    fn unknown_type(&self, name: &str, node: &Node) -> CheckErr {
+        if UNSUPPORTED_TYPING_NAMES.contains(&name) {
+            CheckErr::unsupported(&format!("unsupported type {}", name), node)
+        } else if self.env.var_type(name).is_some() {
+            CheckErr::new_from_node(&format!("'{}' is not a valid type", name), node)
+        } else {
+            CheckErr::undefined_name(name, node)
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use crate::{
+    checker::{CheckErr, Checker},
+    type_var::{ClassType, Param, ParamKind, Place, TypeVar},
+};
+use log::debug;
+use tree_sitter::Node;
+
+/// How a function defined in a class body is bound when accessed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MethodKind {
+    /// Regular method, `self` is bound when accessed through an instance
+    Instance,
+    /// `@staticmethod`, never bound
+    Static,
+    /// `@classmethod`, the class is always bound
+    Class,
+    /// `@property`, accessing it gives the return type
+    Property,
+    /// `@x.setter` or `@x.deleter` for an existing property
+    PropertyAccessor,
+}
+
+impl<'a> Checker<'a> {
+    /// Check a class body inside its own scope
+    /// The class is bound before the body so methods can refer to it
+    pub fn check_class_def(&mut self, node: &Node) -> Result<(), CheckErr> {
+        let name = self.node_text(&self.child(node, "name")?)?;
+        let class_place = Place::from_ts_point(name, node.start_position());
+        let (bases, opaque_base) = self.class_bases(node, &class_place);
+        let cls =
+            ClassType::with_bases(class_place.clone(), &bases, opaque_base).unwrap_or_else(|| {
+                self.report(CheckErr::new_from_node(
+                    &format!(
+                        "Cannot create a consistent method resolution order for '{}'",
+                        name
+                    ),
+                    node,
+                ));
+                ClassType::with_bases(class_place.clone(), &[], true)
+                    .expect("no bases is always consistent")
+            });
+        debug!("class {} mro {:?}", class_place, cls.mro);
+
+        self.env
+            .insert_binding(class_place.clone(), TypeVar::Class(cls.clone()));
+        self.env.insert_var(name, class_place.clone());
+        self.declared_ahead.remove(&class_place);
+
+        let body = self.child(node, "body")?;
+        let _scope_guard = self.env.enter_scope(&cls.scope_name());
+        let outer_class = self.current_class.replace(cls);
+        // a class body in a function doesn't return from it
+        let outer_returns = std::mem::take(&mut self.returns);
+        self.check_node(&body);
+        self.returns = outer_returns;
+        self.current_class = outer_class;
+        Ok(())
+    }
+
+    /// Classes listed as bases, and whether any base couldn't be resolved
+    /// Classes without bases inherit from `object`
+    pub fn class_bases(&mut self, node: &Node, class_place: &Place) -> (Vec<ClassType>, bool) {
+        let mut bases = Vec::new();
+        let mut opaque_base = false;
+        if let Some(args) = node.child_by_field_name("superclasses") {
+            for arg in args.named_children(&mut args.walk()) {
+                if matches!(arg.kind(), "keyword_argument" | "comment") {
+                    continue;
+                }
+                match self.infer_or_any(&arg) {
+                    TypeVar::Class(base) => bases.push(base),
+                    TypeVar::Any => opaque_base = true,
+                    _ => {
+                        let text = self.node_text(&arg).unwrap_or_default();
+                        self.report(CheckErr::unsupported(
+                            &format!("unsupported base class {}", text),
+                            &arg,
+                        ));
+                        opaque_base = true;
+                    }
+                }
+            }
+        }
+        if bases.is_empty()
+            && !opaque_base
+            && let Some(TypeVar::Class(object)) = self.env.var_type("object")
+            && object.place != *class_place
+        {
+            bases.push(object);
+        }
+        (bases, opaque_base)
+    }
+
+    /// Work out how a method is bound from its decorators
+    pub fn method_kind(&self, fn_node: &Node) -> MethodKind {
+        // `__new__` takes the class as a static method without being decorated
+        let name = fn_node.child_by_field_name("name");
+        if name.and_then(|n| self.node_text(&n).ok()) == Some("__new__") {
+            return MethodKind::Static;
+        }
+        let Some(decorated) = fn_node
+            .parent()
+            .filter(|p| p.kind() == "decorated_definition")
+        else {
+            return MethodKind::Instance;
+        };
+        for decorator in decorated.named_children(&mut decorated.walk()) {
+            if decorator.kind() != "decorator" {
+                continue;
+            }
+            let text = self.node_text(&decorator).unwrap_or_default();
+            match text.trim_start_matches('@').trim() {
+                "staticmethod" => return MethodKind::Static,
+                "classmethod" => return MethodKind::Class,
+                "property" => return MethodKind::Property,
+                d if d.ends_with(".setter") || d.ends_with(".deleter") => {
+                    return MethodKind::PropertyAccessor;
+                }
+                _ => {}
+            }
+        }
+        MethodKind::Instance
+    }
+
+    /// Class being defined when it's an enum, names assigned in its body are its members
+    pub fn current_enum(&self) -> Option<ClassType> {
+        let cls = self.current_class.as_ref()?;
+        let Some(TypeVar::Class(enum_cls)) = self.env.builtin_var_type("Enum") else {
+            return None;
+        };
+        cls.mro.contains(&enum_cls.place).then(|| cls.clone())
+    }
+
+    /// Look up an attribute defined on a class or its instances
+    /// Each class in the MRO is searched in order
+    pub fn class_attr(&self, cls: &ClassType, attr: &str) -> Option<TypeVar> {
+        cls.mro
+            .iter()
+            .find_map(|pl| self.env.scope_var_type(&pl.to_string(), attr))
+            .or(cls.opaque_base.then_some(TypeVar::Any))
+    }
+
+    /// Parameters for calling a class, taken from `__init__` without `self`
+    /// `None` when they aren't known, eg. `__init__` comes from a base that couldn't be resolved
+    pub fn constructor_params(&self, cls: &ClassType) -> Option<Vec<Param>> {
+        let defined_at = |attr| {
+            cls.mro
+                .iter()
+                .position(|pl| self.env.scope_var_type(&pl.to_string(), attr).is_some())
+        };
+        // `__new__` takes the arguments when it's defined closer to the class than `__init__`
+        let by_new = match (defined_at("__new__"), defined_at("__init__")) {
+            (Some(new), Some(init)) => new < init,
+            (Some(_), None) => !cls.opaque_base,
+            _ => false,
+        };
+        if by_new {
+            return match self.class_attr(cls, "__new__") {
+                Some(TypeVar::Function(_, params, _)) => match params.first() {
+                    Some(first) if first.is_positional() => Some(params[1..].to_vec()),
+                    _ => Some(params),
+                },
+                _ => None,
+            };
+        }
+        match self
+            .class_attr(cls, "__init__")
+            .map(|init| self.bind_method(init, true))
+        {
+            Some(TypeVar::Function(_, params, _)) => Some(params),
+            Some(_) => None,
+            None => Some(vec![]),
+        }
+    }
+
+    /// Bind `self` or `cls` for methods accessed through an instance or the class
+    pub fn bind_method(&self, attr_ty: TypeVar, via_instance: bool) -> TypeVar {
+        let TypeVar::Function(place, params, ret) = attr_ty else {
+            return attr_ty;
+        };
+        let kind = self
+            .method_kinds
+            .get(&place)
+            .copied()
+            .unwrap_or(MethodKind::Instance);
+        let bound = match kind {
+            MethodKind::Class => true,
+            MethodKind::Instance => via_instance,
+            _ => false,
+        };
+        // `def f(*args)` takes self as part of args
+        let params = match params.first() {
+            Some(first) if bound && first.is_positional() => params.into_iter().skip(1).collect(),
+            _ => params,
+        };
+        TypeVar::Function(place, params, ret)
+    }
+
+    /// Check a method redefined in a subclass can be used wherever the base class method is
+    /// Parameters are contravariant and the return type is covariant,
+    /// return types are only compared when both methods annotate them
+    pub fn check_override(&mut self, cls: &ClassType, name: &str, method: &TypeVar, node: &Node) {
+        // constructors and private names aren't part of the interface
+        let private = name.starts_with("__") && !name.ends_with("__");
+        if private || matches!(name, "__init__" | "__new__" | "__init_subclass__") {
+            return;
+        }
+        let Some((base_place, base_ty)) = cls.mro.iter().skip(1).find_map(|pl| {
+            self.env
+                .scope_var_type(&pl.to_string(), name)
+                .map(|ty| (pl, ty))
+        }) else {
+            return;
+        };
+        let inferred = |scope: &str| {
+            self.env
+                .scope_var(scope, name)
+                .is_some_and(|pl| self.inferred_returns.contains(&pl))
+        };
+        let returns_declared = !inferred(&base_place.to_string()) && !inferred(&cls.scope_name());
+        let base = self.bind_method(base_ty, true);
+        let over = self.bind_method(method.clone(), true);
+        let Some(reason) = Self::override_conflict(&base, &over, returns_declared) else {
+            return;
+        };
+        let mut err = CheckErr::new_from_node(
+            &format!(
+                "Signature of '{}' is incompatible with supertype '{}', {}",
+                name, base_place.name, reason
+            ),
+            node,
+        );
+        if let TypeVar::Function(pl, ..) = &base
+            && !self.env.is_builtin_scope(&base_place.to_string())
+        {
+            err = err.with_note(
+                &format!("'{}' is defined in '{}' here", name, base_place.name),
+                pl.clone(),
+            );
+        }
+        self.report(err);
+    }
+
+    /// Why `over` can't replace `base`, both are bound so `self` isn't compared
+    fn override_conflict(base: &TypeVar, over: &TypeVar, returns_declared: bool) -> Option<String> {
+        match (base, over) {
+            (TypeVar::Any, _) | (_, TypeVar::Any) => None,
+            (TypeVar::Function(_, base_params, base_ret), TypeVar::Function(_, params, ret)) => {
+                if let Some(reason) = Self::params_conflict(base_params, params) {
+                    return Some(reason);
+                }
+                if !returns_declared {
+                    return None;
+                }
+                let base_ret = TypeVar::union(base_ret.clone());
+                let ret = TypeVar::union(ret.clone());
+                (!base_ret.type_check(&ret))
+                    .then(|| format!("return type expected {} found {}", base_ret, ret))
+            }
+            (TypeVar::Function(..), _) | (_, TypeVar::Function(..)) => {
+                Some(format!("expected {} found {}", base, over))
+            }
+            // a property gives its return type
+            _ if !returns_declared => None,
+            _ => (!base.type_check(over)).then(|| format!("expected {} found {}", base, over)),
+        }
+    }
+
+    /// Why the parameters `params` can't accept every call `base_params` accepts
+    fn params_conflict(base_params: &[Param], params: &[Param]) -> Option<String> {
+        let has_kind = |ps: &[Param], kind| ps.iter().find(|p| p.kind == kind).cloned();
+        let base_positional: Vec<&Param> =
+            base_params.iter().filter(|p| p.is_positional()).collect();
+        let positional: Vec<&Param> = params.iter().filter(|p| p.is_positional()).collect();
+        let args = has_kind(params, ParamKind::VarPositional);
+        let kwargs = has_kind(params, ParamKind::VarKeyword);
+
+        for base_param in base_params {
+            // where the override takes the argument the base method is called with
+            let param = match base_param.kind {
+                ParamKind::VarPositional => match &args {
+                    Some(args) => args.clone(),
+                    None => return Some(format!("missing parameter *{}", base_param.name)),
+                },
+                ParamKind::VarKeyword => match &kwargs {
+                    Some(kwargs) => kwargs.clone(),
+                    None => return Some(format!("missing parameter **{}", base_param.name)),
+                },
+                ParamKind::KeywordOnly => {
+                    match params
+                        .iter()
+                        .find(|p| p.is_keyword() && p.name == base_param.name)
+                    {
+                        Some(p) => p.clone(),
+                        None => match &kwargs {
+                            Some(kwargs) => kwargs.clone(),
+                            None => {
+                                return Some(format!("missing parameter '{}'", base_param.name));
+                            }
+                        },
+                    }
+                }
+                _ => {
+                    let i = base_positional
+                        .iter()
+                        .position(|p| p == &base_param)
+                        .unwrap_or_default();
+                    match positional.get(i) {
+                        Some(p) => {
+                            if base_param.kind == ParamKind::Normal
+                                && (p.name != base_param.name || !p.is_keyword())
+                                && kwargs.is_none()
+                            {
+                                return Some(format!(
+                                    "parameter '{}' can't be passed by keyword",
+                                    base_param.name
+                                ));
+                            }
+                            (*p).clone()
+                        }
+                        None => match &args {
+                            Some(args) => args.clone(),
+                            None => {
+                                return Some(format!("missing parameter '{}'", base_param.name));
+                            }
+                        },
+                    }
+                }
+            };
+            if base_param.has_default && !param.has_default && param.is_required() {
+                return Some(format!("parameter '{}' needs a default", param.name));
+            }
+            // every argument accepted by the base method has to be accepted by the override
+            if !param.ty.type_check(&base_param.ty) {
+                return Some(format!(
+                    "parameter '{}' expected {} found {}",
+                    base_param.name, base_param.ty, param.ty
+                ));
+            }
+        }
+
+        // new parameters have to be optional so calls to the base method still work
+        let new_keyword = |p: &&Param| {
+            p.kind == ParamKind::KeywordOnly
+                && !base_params
+                    .iter()
+                    .any(|b| b.is_keyword() && b.name == p.name)
+        };
+        let added = positional
+            .iter()
+            .skip(base_positional.len())
+            .copied()
+            .chain(params.iter().filter(new_keyword))
+            .find(|p| p.is_required());
+        added.map(|p| format!("added required parameter '{}'", p.name))
+    }
+
+    /// Type of `obj.attr`, reports an error when the attribute doesn't exist
+    pub fn infer_attribute(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let obj = self.child(node, "object")?;
+        let attr = self.node_text(&self.child(node, "attribute")?)?;
+        let obj_ty = self.infer_type_for_node(&obj)?;
+        self.attribute_of(&obj_ty, attr, node)
+    }
+
+    fn attribute_of(
+        &mut self,
+        obj_ty: &TypeVar,
+        attr: &str,
+        node: &Node,
+    ) -> Result<TypeVar, CheckErr> {
+        match obj_ty {
+            TypeVar::Instance(cls) => match self.class_attr(cls, attr) {
+                Some(ty) => Ok(self.bind_method(ty, true)),
+                None => Err(CheckErr::new_from_node(
+                    &format!("'{}' object has no attribute '{}'", cls.name(), attr),
+                    node,
+                )),
+            },
+            TypeVar::Class(cls) => match self.class_attr(cls, attr) {
+                Some(ty) => Ok(self.bind_method(ty, false)),
+                None => Err(CheckErr::new_from_node(
+                    &format!("type object '{}' has no attribute '{}'", cls.name(), attr),
+                    node,
+                )),
+            },
+            TypeVar::Literal(value) => self.attribute_of(&value.base_type(), attr, node),
+            TypeVar::None => Err(CheckErr::new_from_node(
+                &format!("'None' has no attribute '{}'", attr),
+                node,
+            )),
+            TypeVar::Union(members) => {
+                let attr_types: Result<Vec<TypeVar>, CheckErr> = members
+                    .iter()
+                    .map(|m| self.attribute_of(m, attr, node))
+                    .collect();
+                Ok(TypeVar::union(attr_types?))
+            }
+            TypeVar::Dict(key, value) => Ok(match self.method_of(obj_ty, attr) {
+                Some(TypeVar::Function(place, params, ret)) => {
+                    let ret = Self::dict_view(attr, key, value).map_or(ret, |view| vec![view]);
+                    TypeVar::Function(place, params, ret)
+                }
+                Some(method) => method,
+                None => TypeVar::Any,
+            }),
+            // attributes of other types aren't known yet
+            _ => Ok(TypeVar::Any),
+        }
+    }
+
+    /// What `keys()`, `values()` and `items()` of a dict give
+    /// The stubs can't describe element types, the views are typed as lists of them
+    fn dict_view(method: &str, key: &TypeVar, value: &TypeVar) -> Option<TypeVar> {
+        let elem = match method {
+            "keys" => key.clone(),
+            "values" => value.clone(),
+            "items" => TypeVar::Tuple(vec![key.clone(), value.clone()]),
+            _ => return None,
+        };
+        Some(TypeVar::List(Box::new(elem)))
+    }
+
+    /// Assignment to `obj.attr`
+    /// Inside a method `self.attr = ...` defines a new attribute on the class
+    pub fn check_attribute_assignment(
+        &mut self,
+        node: &Node,
+        lhs: &Node,
+        rhs_type: Option<TypeVar>,
+    ) -> Result<(), CheckErr> {
+        let obj = self.child(lhs, "object")?;
+        let attr = self.node_text(&self.child(lhs, "attribute")?)?;
+        let declared = node.child_by_field_name("type").map(|type_node| {
+            self.annotation_type(&type_node).unwrap_or_else(|err| {
+                self.report(err);
+                TypeVar::Any
+            })
+        });
+
+        let obj_ty = self.infer_or_any(&obj);
+        let attr_place = Place::from_ts_point(attr, lhs.start_position());
+        // the assignment that declared the attribute before the methods were checked gives its type
+        if let TypeVar::Instance(cls) = &obj_ty
+            && self.method_class.as_ref() == Some(cls)
+            && (self.class_attr(cls, attr).is_none()
+                || self.env.scope_var(&cls.scope_name(), attr).as_ref() == Some(&attr_place))
+        {
+            if let (Some(declared), Some(rhs_type), Some(rhs)) =
+                (&declared, &rhs_type, node.child_by_field_name("right"))
+                && !Self::value_fits(declared, &rhs, rhs_type)
+            {
+                self.report(CheckErr::new_from_node(
+                    &format!(
+                        "Mismatched types while assigning to '{}' expected {} found {}",
+                        self.node_text(lhs)?,
+                        declared,
+                        rhs_type
+                    ),
+                    node,
+                ));
+            }
+            // the attribute could be reassigned, only the type of the value is kept
+            // `None` is only a placeholder when other methods assign the value
+            let ty = match (declared, rhs_type) {
+                (Some(declared), _) => declared,
+                (None, Some(TypeVar::None)) if self.reassigned_attrs.contains(&attr_place) => {
+                    TypeVar::Any
+                }
+                (None, rhs_type) => rhs_type.map_or(TypeVar::Any, |ty| ty.widened()),
+            };
+            debug!("new attribute {}.{} {}", cls.name(), attr, ty);
+            self.env
+                .insert_scope_var(&cls.scope_name(), attr, attr_place, ty);
+            return Ok(());
+        }
+
+        let target_ty = self.infer_type_for_node(lhs)?;
+        if let Some(rhs_type) = rhs_type
+            && !target_ty.type_check(&rhs_type)
+        {
+            return Err(CheckErr::new_from_node(
+                &format!(
+                    "Mismatched types while assigning to '{}' expected {} found {}",
+                    self.node_text(lhs)?,
+                    target_ty,
+                    rhs_type
+                ),
+                node,
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{
+    ast::{imported_ids, imported_name},
+    checker::{CheckErr, Checker, class::MethodKind},
+    type_var::{ClassType, Param, ParamKind, Place, TypeVar},
+};
+use log::debug;
+use std::collections::HashMap;
+use tree_sitter::Node;
+
+/// Times the body of a recursive function is checked while its return type settles
+const MAX_RECURSION_PASSES: usize = 4;
+
+/// Classes of the `enum` module the builtins stub defines
+const ENUM_CLASSES: &[&str] = &["Enum", "IntEnum", "StrEnum"];
+
+impl<'a> Checker<'a> {
+    /// Declare the definitions in `block` then check its statements in order
+    /// Functions and classes can be used before the line defining them, eg. by functions calling each other
+    /// Returns whether the end of the block can be reached
+    pub fn check_block(&mut self, block: &Node) -> bool {
+        self.declare_block(block);
+        let stmts: Vec<Node> = block.named_children(&mut block.walk()).collect();
+        self.check_flow(&stmts)
+    }
+
+    /// Bind the signature of every `def` and `class` directly in `block`
+    /// Classes are declared first so annotations can refer to classes defined later
+    /// In class bodies annotated attributes like `x: int` are declared too
+    fn declare_block(&mut self, block: &Node) {
+        self.declare_imports(block);
+        let defs: Vec<Node> = block
+            .named_children(&mut block.walk())
+            .filter_map(|stmt| match stmt.kind() {
+                "decorated_definition" => stmt.child_by_field_name("definition"),
+                _ => Some(stmt),
+            })
+            .collect();
+        let mut classes = Vec::new();
+        for def in defs.iter().filter(|d| d.kind() == "class_definition") {
+            match self.declare_class(def) {
+                Ok(cls) => classes.push((cls, *def)),
+                Err(err) => self.report(err),
+            }
+        }
+        for def in &defs {
+            let result = match def.kind() {
+                "function_definition" => self.declare_function(def),
+                "expression_statement" if self.current_class.is_some() => {
+                    self.declare_attribute(def)
+                }
+                _ => Ok(()),
+            };
+            if let Err(err) = result {
+                self.report(err);
+            }
+        }
+        // using a definition directly is an error until its statement runs,
+        // declarations like the bases of a class can use each other
+        for def in &defs {
+            if matches!(def.kind(), "function_definition" | "class_definition")
+                && let Some(name) = def.child_by_field_name("name")
+                && let Ok(name) = self.node_text(&name)
+            {
+                let place = Place::from_ts_point(name, def.start_position());
+                self.declared_ahead.insert(place);
+            }
+        }
+        // methods are declared once every class around them is
+        for (cls, def) in classes {
+            let Some(body) = def.child_by_field_name("body") else {
+                continue;
+            };
+            let _scope_guard = self.env.enter_scope(&cls.scope_name());
+            let outer_class = self.current_class.replace(cls.clone());
+            self.declare_block(&body);
+            self.declare_self_attributes(&cls, &body);
+            self.current_class = outer_class;
+        }
+    }
+
+    /// Bind the names imported anywhere in `node` outside of nested definitions
+    /// The bases of a class are looked up before the imports above it run
+    fn declare_imports(&mut self, node: &Node) {
+        for child in node.named_children(&mut node.walk()) {
+            match child.kind() {
+                "import_statement" | "import_from_statement" => self.bind_import(&child),
+                "function_definition" | "class_definition" | "decorated_definition" => {}
+                _ => self.declare_imports(&child),
+            }
+        }
+    }
+
+    /// Bind the names an import gives
+    /// Modules aren't followed yet, the names they define could be anything
+    /// except for the enum classes the stub defines
+    pub fn bind_import(&mut self, stmt: &Node) {
+        let module = stmt
+            .child_by_field_name("module_name")
+            .and_then(|m| self.node_text(&m).ok());
+        for id in imported_ids(stmt) {
+            let ty = match module {
+                Some("enum") => imported_name(&id)
+                    .and_then(|name| self.node_text(&name).ok())
+                    .filter(|name| ENUM_CLASSES.contains(name))
+                    .and_then(|name| self.env.builtin_var_type(name)),
+                _ => None,
+            };
+            self.bind_target(&id, &ty.unwrap_or(TypeVar::Any));
+        }
+    }
+
+    fn declare_class(&mut self, node: &Node) -> Result<ClassType, CheckErr> {
+        let name = self.node_text(&self.child(node, "name")?)?;
+        let class_place = Place::from_ts_point(name, node.start_position());
+        let (bases, opaque_base) = self.class_bases(node, &class_place);
+        // an inconsistent order is reported when the class is checked
+        let cls = ClassType::with_bases(class_place.clone(), &bases, opaque_base)
+            .or_else(|| ClassType::with_bases(class_place.clone(), &[], true))
+            .ok_or_else(|| CheckErr::internal("no bases is always consistent", node))?;
+        self.env
+            .insert_binding(class_place.clone(), TypeVar::Class(cls.clone()));
+        self.env.insert_var(name, class_place);
+        Ok(cls)
+    }
+
+    /// Signature of a function from its annotations, its body isn't looked at
+    /// Unannotated parameters accept anything until the function is checked
+    /// and an unannotated return is `Any`
+    fn declare_function(&mut self, node: &Node) -> Result<(), CheckErr> {
+        let fn_name = self.node_text(&self.child(node, "name")?)?;
+        let fn_place = Place::from_ts_point(fn_name, node.start_position());
+        let kind = match self.current_class {
+            Some(_) => self.method_kind(node),
+            None => MethodKind::Static,
+        };
+        let owner = self.current_class.clone();
+        let mut params = Vec::new();
+        for (p_node, id_node, p_kind) in self.param_kinds(&self.child(node, "parameters")?) {
+            let ty = match (p_node.child_by_field_name("type"), &owner) {
+                // errors in annotations are reported when the function is checked
+                (Some(ty), _) => self.annotation_type(&ty).unwrap_or(TypeVar::Any),
+                (None, Some(cls)) if params.is_empty() && p_kind == ParamKind::Normal => {
+                    Self::bound_param_type(cls, kind)
+                }
+                _ => TypeVar::Any,
+            };
+            params.push(Param {
+                name: self.node_text(&id_node)?.to_owned(),
+                kind: p_kind,
+                has_default: p_node.child_by_field_name("value").is_some(),
+                ty,
+            });
+        }
+        let return_type = node
+            .child_by_field_name("return_type")
+            .and_then(|ty| self.annotation_type(&ty).ok())
+            .unwrap_or(TypeVar::Any);
+        let fn_type = match kind {
+            MethodKind::PropertyAccessor => return Ok(()),
+            MethodKind::Property => return_type,
+            _ => TypeVar::Function(fn_place.clone(), params, vec![return_type]),
+        };
+        debug!("declared {} {}", fn_place, fn_type);
+        if kind != MethodKind::Instance && owner.is_some() {
+            self.method_kinds.insert(fn_place.clone(), kind);
+        }
+        self.env.insert_binding(fn_place.clone(), fn_type);
+        self.env.insert_var(fn_name, fn_place);
+        Ok(())
+    }
+
+    /// Class attribute declared with an annotation, `x: int` or `x: int = 0`
+    fn declare_attribute(&mut self, stmt: &Node) -> Result<(), CheckErr> {
+        let Some(assign) = stmt.named_child(0).filter(|n| n.kind() == "assignment") else {
+            return Ok(());
+        };
+        let (Some(lhs), Some(type_node)) = (
+            assign.child_by_field_name("left"),
+            assign.child_by_field_name("type"),
+        ) else {
+            return Ok(());
+        };
+        // `Final` takes its type from the value
+        if lhs.kind() != "identifier" || self.final_annotation(&type_node).is_some() {
+            return Ok(());
+        }
+        let Ok(ty) = self.annotation_type(&type_node) else {
+            return Ok(());
+        };
+        let id = self.node_text(&lhs)?;
+        let place = Place::from_ts_point(id, lhs.start_position());
+        self.env.insert_binding(place.clone(), ty);
+        self.env.insert_var(id, place);
+        Ok(())
+    }
+
+    /// Attributes the methods of a class assign through `self`, eg. `self.x = 0` in `__init__`
+    /// They are `Any` unless annotated until their assignment is checked,
+    /// so methods can use them whatever order they are defined in
+    /// Attributes assigned again elsewhere are recorded, a `None` placeholder doesn't decide their type
+    fn declare_self_attributes(&mut self, cls: &ClassType, body: &Node) {
+        let mut methods: Vec<Node> = body
+            .named_children(&mut body.walk())
+            .filter_map(|stmt| match stmt.kind() {
+                "decorated_definition" => stmt.child_by_field_name("definition"),
+                _ => Some(stmt),
+            })
+            .filter(|def| def.kind() == "function_definition")
+            .filter(|def| {
+                matches!(
+                    self.method_kind(def),
+                    MethodKind::Instance | MethodKind::Property | MethodKind::PropertyAccessor
+                )
+            })
+            .collect();
+        // the attributes `__init__` assigns are the ones the rest of the class expects
+        methods.sort_by_key(|def| {
+            let name = def.child_by_field_name("name");
+            name.and_then(|n| self.node_text(&n).ok()) != Some("__init__")
+        });
+        let mut declaring: HashMap<&str, Place> = HashMap::new();
+        for def in methods {
+            let self_name = def
+                .child_by_field_name("parameters")
+                .and_then(|params| params.named_child(0))
+                .and_then(|param| self.param_name_node(&param))
+                .and_then(|id| self.node_text(&id).ok());
+            let (Some(self_name), Some(fn_body)) = (self_name, def.child_by_field_name("body"))
+            else {
+                continue;
+            };
+            let mut assignments = Vec::new();
+            collect_assignments(&fn_body, &mut assignments);
+            for assign in assignments {
+                let Some(lhs) = assign
+                    .child_by_field_name("left")
+                    .filter(|lhs| lhs.kind() == "attribute")
+                else {
+                    continue;
+                };
+                let (Some(obj), Some(attr_node)) = (
+                    lhs.child_by_field_name("object"),
+                    lhs.child_by_field_name("attribute"),
+                ) else {
+                    continue;
+                };
+                let (Ok(obj), Ok(attr)) = (self.node_text(&obj), self.node_text(&attr_node)) else {
+                    continue;
+                };
+                if obj != self_name {
+                    continue;
+                }
+                if let Some(place) = declaring.get(attr) {
+                    self.reassigned_attrs.insert(place.clone());
+                    continue;
+                }
+                if self.class_attr(cls, attr).is_some() {
+                    continue;
+                }
+                // errors in annotations are reported when the assignment is checked
+                let ty = assign
+                    .child_by_field_name("type")
+                    .and_then(|ty| self.annotation_type(&ty).ok())
+                    .unwrap_or(TypeVar::Any);
+                let place = Place::from_ts_point(attr, lhs.start_position());
+                declaring.insert(attr, place.clone());
+                self.env
+                    .insert_scope_var(&cls.scope_name(), attr, place, ty);
+            }
+        }
+    }
+
+    /// Returns of an unannotated function body
+    /// Recursive calls first return nothing, then the body is checked again with the
+    /// returns found so far until they stop changing
+    pub fn infer_returns(
+        &mut self,
+        body: &Node,
+        fn_place: &Place,
+        params: &[Param],
+    ) -> Vec<TypeVar> {
+        // a generator gives an iterator over what it yields, which can't be described yet
+        if yields(body) {
+            self.infer_fn_body(body, None);
+            return vec![TypeVar::Any];
+        }
+        if !self.mentions_name(body, &fn_place.name) {
+            return self.infer_fn_body(body, None).found;
+        }
+        let mut found = vec![TypeVar::Union(vec![])];
+        let params_state = self.env.local_vars();
+        for pass in 1..=MAX_RECURSION_PASSES {
+            self.env.set_local_vars(params_state.clone());
+            // bound in the function scope, it shadows the declared signature inside the body
+            let fn_type = TypeVar::Function(fn_place.clone(), params.to_vec(), found.clone());
+            self.env.insert_binding(fn_place.clone(), fn_type);
+            let errors_start = self.errors.len();
+            let next = self.infer_fn_body(body, None).found;
+            // `Any` is equivalent to every type, so the types have to be the same
+            let settled = TypeVar::union(next.clone()) == TypeVar::union(found);
+            if settled || pass == MAX_RECURSION_PASSES {
+                return next;
+            }
+            // recursive calls returned too little in this pass, eg. nothing in the first one
+            self.errors.truncate(errors_start);
+            found = next;
+        }
+        found
+    }
+
+    /// Whether `name` is a function or class of the innermost scope whose definition hasn't run yet
+    pub fn is_declared_ahead(&self, name: &str) -> bool {
+        self.env
+            .local_var(name)
+            .is_some_and(|pl| self.declared_ahead.contains(&pl))
+    }
+
+    /// Whether the name `name` is used anywhere in `node`
+    pub fn mentions_name(&self, node: &Node, name: &str) -> bool {
+        if node.kind() == "identifier" {
+            return self.node_text(node).is_ok_and(|text| text == name);
+        }
+        node.named_children(&mut node.walk())
+            .any(|child| self.mentions_name(&child, name))
+    }
+}
+
+/// Whether a function body has a `yield`, which makes the function a generator
+fn yields(node: &Node) -> bool {
+    node.named_children(&mut node.walk())
+        .any(|child| match child.kind() {
+            "function_definition" | "class_definition" | "lambda" => false,
+            "yield" => true,
+            _ => yields(&child),
+        })
+}
+
+/// Assignments anywhere in a function body, nested functions and classes are left out
+fn collect_assignments<'t>(node: &Node<'t>, found: &mut Vec<Node<'t>>) {
+    for child in node.named_children(&mut node.walk()) {
+        match child.kind() {
+            "function_definition" | "class_definition" | "lambda" => continue,
+            "assignment" => found.push(child),
+            _ => {}
+        }
+        collect_assignments(&child, found);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::{
+    ast::imported_ids,
+    cfg::{Cfg, Step},
+    checker::{CheckErr, Checker},
+    type_var::{Place, TypeVar},
+};
+use log::debug;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use tree_sitter::Node;
+
+/// Place holding the current value of each var in the innermost scope
+type FlowState = HashMap<String, Place>;
+
+/// Type of each var and whether it's possibly unbound
+type StateTypes = HashMap<String, (TypeVar, bool)>;
+
+/// Times the type of a var entering a block can change before it becomes `Any`
+/// Types that keep growing never settle, eg. a list that nests itself in a loop
+const MAX_TYPE_CHANGES: usize = 5;
+
+impl<'a> Checker<'a> {
+    /// Check statements in the order they can run
+    /// Where paths join, a var assigned differently along them has the union of their types
+    /// Returns whether the end of the statements can be reached
+    pub fn check_flow(&mut self, stmts: &[Node]) -> bool {
+        let cfg = Cfg::build(stmts);
+        // state leaving a block along each of its edges, keyed by block and edge index
+        let mut edge_states: HashMap<(usize, usize), FlowState> = HashMap::new();
+        // types of the vars entering each block the last time it was checked
+        let mut entry_types: Vec<Option<StateTypes>> = vec![None; cfg.blocks.len()];
+        // times the type of each var entering a block changed, and the vars made `Any` there
+        let mut changes: Vec<HashMap<String, usize>> = vec![HashMap::new(); cfg.blocks.len()];
+        let mut widened: Vec<HashSet<String>> = vec![HashSet::new(); cfg.blocks.len()];
+        // errors from the last time each block was checked, earlier passes saw incomplete types
+        let mut block_errors: Vec<Vec<CheckErr>> = vec![Vec::new(); cfg.blocks.len()];
+        let initial = self.env.local_vars();
+
+        // lower blocks come first in the source, so loops settle before the code after them
+        let mut worklist = BTreeSet::from([0]);
+        while let Some(idx) = worklist.pop_first() {
+            let mut state = match idx {
+                0 => initial.clone(),
+                _ => self.join_states(&cfg, idx, &edge_states),
+            };
+            let mut types = self.state_types(&state);
+            if let Some(previous) = &entry_types[idx] {
+                for (name, ty) in &types {
+                    if previous.get(name) == Some(ty) {
+                        continue;
+                    }
+                    let count = changes[idx].entry(name.clone()).or_default();
+                    *count += 1;
+                    if *count > MAX_TYPE_CHANGES {
+                        widened[idx].insert(name.clone());
+                    }
+                }
+            }
+            if !widened[idx].is_empty() {
+                self.widen_state(&mut state, &widened[idx], &cfg, idx);
+                types = self.state_types(&state);
+            }
+            if entry_types[idx].as_ref() == Some(&types) {
+                continue;
+            }
+            debug!("flow block {}", idx);
+            entry_types[idx] = Some(types);
+
+            let errors_start = self.errors.len();
+            self.env.set_local_vars(state);
+            for step in &cfg.blocks[idx].steps {
+                self.check_step(step);
+            }
+            let exit_state = self.env.local_vars();
+            for (edge_idx, edge) in cfg.blocks[idx].succs.iter().enumerate() {
+                if let Some((cond, positive)) = edge.condition {
+                    let narrowing = self.narrow_condition(&cond, positive);
+                    let at = if positive {
+                        cond.start_position()
+                    } else {
+                        cond.end_position()
+                    };
+                    self.apply_narrowing(&narrowing, at);
+                }
+                edge_states.insert((idx, edge_idx), self.env.local_vars());
+                self.env.set_local_vars(exit_state.clone());
+                worklist.insert(edge.to);
+            }
+            block_errors[idx] = self.errors.split_off(errors_start);
+        }
+        for err in block_errors.into_iter().flatten() {
+            self.report(err);
+        }
+
+        // later statements see the vars of every path reaching the end
+        let reachable = entry_types[cfg.exit].is_some();
+        if reachable {
+            let mut state = self.join_states(&cfg, cfg.exit, &edge_states);
+            self.widen_state(&mut state, &widened[cfg.exit], &cfg, cfg.exit);
+            self.env.set_local_vars(state);
+        }
+        reachable
+    }
+
+    fn check_step(&mut self, step: &Step) {
+        match step {
+            Step::Node(node) => self.check_node(node),
+            Step::ForIter(stmt) => {
+                let Some(iterable) = stmt.child_by_field_name("right") else {
+                    return;
+                };
+                self.check_node(&iterable);
+                let ty = self.infer_or_any(&iterable);
+                let element = self.element_type_or_any(&ty, &iterable);
+                self.loop_elements.insert(stmt.id(), element);
+            }
+            Step::ForTarget(stmt) => {
+                let element = self
+                    .loop_elements
+                    .get(&stmt.id())
+                    .cloned()
+                    .unwrap_or(TypeVar::Any);
+                if let Some(target) = stmt.child_by_field_name("left") {
+                    self.bind_target(&target, &element);
+                }
+            }
+            Step::WithItem(item) => self.check_with_item(item),
+            Step::Except(clause) => self.check_except(clause),
+            Step::Case(clause) => {
+                // what the subject is narrowed to by the pattern isn't modelled
+                let mut captures = Vec::new();
+                pattern_ids(*clause, &mut captures);
+                for id in captures {
+                    self.bind_target(&id, &TypeVar::Any);
+                }
+            }
+        }
+    }
+
+    /// Check the exceptions an `except` clause catches and bind its `as` target
+    /// The target is an instance of the class caught, or of any of a tuple of classes
+    fn check_except(&mut self, clause: &Node) {
+        let Some(value) = clause.child_by_field_name("value") else {
+            return;
+        };
+        let (caught, target) = match value.kind() {
+            "as_pattern" => (value.named_child(0), as_target(&value)),
+            _ => (Some(value), None),
+        };
+        let Some(caught) = caught else {
+            return;
+        };
+        // `except* E` catches a group of the exceptions, the group isn't modelled
+        let group = caught.kind() == "list_splat";
+        let Some(caught) = (if group {
+            caught.named_child(0)
+        } else {
+            Some(caught)
+        }) else {
+            return;
+        };
+        self.check_node(&caught);
+        let ty = self.infer_or_any(&caught);
+        if let Some(target) = target {
+            let exception = match group {
+                true => TypeVar::Any,
+                false => self.caught_type(&ty),
+            };
+            self.bind_target(&target, &exception);
+        }
+    }
+
+    /// Instance caught by `except` for the classes `ty`
+    fn caught_type(&self, ty: &TypeVar) -> TypeVar {
+        match ty {
+            TypeVar::Class(cls) => self.instance_type(cls),
+            TypeVar::Tuple(elems) => {
+                TypeVar::union(elems.iter().map(|e| self.caught_type(e)).collect())
+            }
+            _ => TypeVar::Any,
+        }
+    }
+
+    /// Check the context manager of a `with` and bind its `as` target
+    /// The target gets what `__enter__` returns, or `Any` when that isn't known
+    fn check_with_item(&mut self, item: &Node) {
+        let Some(value) = item.child_by_field_name("value") else {
+            return;
+        };
+        let (manager, target) = match value.kind() {
+            "as_pattern" => (value.named_child(0), as_target(&value)),
+            _ => (Some(value), None),
+        };
+        let Some(manager) = manager else {
+            return;
+        };
+        self.check_node(&manager);
+        let ty = self.infer_or_any(&manager);
+        if let Some(target) = target {
+            let entered = self.call_method(&ty, "__enter__").unwrap_or(TypeVar::Any);
+            self.bind_target(&target, &entered);
+        }
+    }
+
+    /// Elements of iterating over `ty`, reporting when it isn't iterable
+    pub fn element_type_or_any(&mut self, ty: &TypeVar, node: &Node) -> TypeVar {
+        self.element_type(ty).unwrap_or_else(|| {
+            self.report(CheckErr::new_from_node(
+                &format!("{} object is not iterable", ty),
+                node,
+            ));
+            TypeVar::Any
+        })
+    }
+
+    /// Bind every name in an assignment target like `x` or `a, (b, c)` to `ty`
+    /// Tuples are unpacked into targets of the same length, other values are iterated
+    pub fn bind_target(&mut self, target: &Node, ty: &TypeVar) {
+        match target.kind() {
+            "identifier" => {
+                let Ok(id) = self.node_text(target) else {
+                    return;
+                };
+                let place = Place::from_ts_point(id, target.start_position());
+                self.env.insert_binding(place.clone(), ty.widened());
+                self.env.insert_var(id, place);
+            }
+            "parenthesized_expression" => {
+                for child in target.named_children(&mut target.walk()) {
+                    self.bind_target(&child, ty);
+                }
+            }
+            "pattern_list" | "tuple_pattern" | "list_pattern" | "tuple" | "list" => {
+                let parts: Vec<Node> = target.named_children(&mut target.walk()).collect();
+                let types = match ty {
+                    TypeVar::Tuple(elems) if elems.len() == parts.len() => elems.clone(),
+                    _ => vec![self.element_type_or_any(ty, target); parts.len()],
+                };
+                for (part, ty) in parts.iter().zip(&types) {
+                    self.bind_target(part, ty);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Names assigned anywhere in a function or module body, except ones declared `global` or `nonlocal`
+    /// Nested functions and classes only assign their own name
+    pub fn local_names(&self, body: &Node) -> HashSet<String> {
+        let mut assigned = Vec::new();
+        let mut outer = HashSet::new();
+        self.collect_assigned(body, &mut assigned, &mut outer);
+        assigned
+            .iter()
+            .filter_map(|id| self.node_text(id).ok())
+            .filter(|name| !outer.contains(name))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn collect_assigned<'t>(
+        &self,
+        node: &Node<'t>,
+        assigned: &mut Vec<Node<'t>>,
+        outer: &mut HashSet<&'a str>,
+    ) {
+        for child in node.named_children(&mut node.walk()) {
+            match child.kind() {
+                "function_definition" | "class_definition" => {
+                    assigned.extend(child.child_by_field_name("name"));
+                    continue;
+                }
+                // these have a scope of their own
+                "lambda"
+                | "list_comprehension"
+                | "set_comprehension"
+                | "dictionary_comprehension"
+                | "generator_expression" => continue,
+                "assignment" | "augmented_assignment" | "for_statement" => {
+                    if let Some(left) = child.child_by_field_name("left") {
+                        target_ids(left, assigned);
+                    }
+                }
+                "import_statement" | "import_from_statement" => {
+                    assigned.extend(imported_ids(&child));
+                }
+                "with_item" | "except_clause" => {
+                    let value = child.child_by_field_name("value");
+                    if let Some(target) = value.as_ref().and_then(as_target) {
+                        target_ids(target, assigned);
+                    }
+                }
+                "named_expression" => {
+                    assigned.extend(child.child_by_field_name("name"));
+                }
+                "case_clause" => pattern_ids(child, assigned),
+                "global_statement" | "nonlocal_statement" => {
+                    for id in child.named_children(&mut child.walk()) {
+                        outer.extend(self.node_text(&id).ok());
+                    }
+                }
+                _ => {}
+            }
+            self.collect_assigned(&child, assigned, outer);
+        }
+    }
+
+    /// Whether the var `name` of the innermost scope isn't assigned along some paths
+    pub fn is_possibly_unbound(&self, name: &str) -> bool {
+        self.env
+            .local_var(name)
+            .is_some_and(|pl| self.possibly_unbound.contains(&pl))
+    }
+
+    /// Vars entering block `idx` from every edge that has been taken to it
+    /// A var with a different place along some edges gets a new place holding the union,
+    /// if some edges don't assign a local var at all the new place is possibly unbound
+    /// Vars of enclosing scopes, eg. narrowed or declared `global`, keep their outer place there
+    fn join_states(
+        &mut self,
+        cfg: &Cfg,
+        idx: usize,
+        edge_states: &HashMap<(usize, usize), FlowState>,
+    ) -> FlowState {
+        let incoming: Vec<&FlowState> = cfg
+            .preds(idx)
+            .filter_map(|(from, edge_idx)| edge_states.get(&(from, edge_idx)))
+            .collect();
+        let names: BTreeSet<&String> = incoming.iter().flat_map(|s| s.keys()).collect();
+        let mut joined = FlowState::new();
+        for name in names {
+            let mut places: Vec<&Place> = incoming.iter().filter_map(|s| s.get(name)).collect();
+            let mut missing = places.len() < incoming.len();
+            let outer = self.env.enclosing_var(name);
+            if missing && !self.env.is_local_name(name) {
+                missing = false;
+                places.extend(outer.as_ref());
+            }
+            let Some(first) = places.first() else {
+                continue;
+            };
+            if !missing && places.iter().all(|pl| pl == first) {
+                joined.insert(name.clone(), (*first).clone());
+                continue;
+            }
+            let ty = TypeVar::union(
+                places
+                    .iter()
+                    .map(|pl| self.env.lookup_binding(pl).unwrap_or(TypeVar::Any))
+                    .collect(),
+            );
+            let merged =
+                Place::from_ts_point(&format!("joined {} {}", name, idx), cfg.blocks[idx].at);
+            debug!("joined {} -> {}", merged, ty);
+            self.env.insert_binding(merged.clone(), ty);
+            if missing {
+                self.possibly_unbound.insert(merged.clone());
+            } else {
+                self.possibly_unbound.remove(&merged);
+            }
+            joined.insert(name.clone(), merged);
+        }
+        joined
+    }
+
+    /// Give the vars in `names` entering block `idx` new places holding `Any`
+    /// They stay possibly unbound
+    fn widen_state(
+        &mut self,
+        state: &mut FlowState,
+        names: &HashSet<String>,
+        cfg: &Cfg,
+        idx: usize,
+    ) {
+        for name in names {
+            let Some(place) = state.get_mut(name) else {
+                continue;
+            };
+            let unbound = self.possibly_unbound.contains(place);
+            let any =
+                Place::from_ts_point(&format!("widened {} {}", name, idx), cfg.blocks[idx].at);
+            self.env.insert_binding(any.clone(), TypeVar::Any);
+            if unbound {
+                self.possibly_unbound.insert(any.clone());
+            } else {
+                self.possibly_unbound.remove(&any);
+            }
+            *place = any;
+        }
+    }
+
+    /// Type of each var and whether it's possibly unbound
+    fn state_types(&self, state: &FlowState) -> StateTypes {
+        state
+            .iter()
+            .map(|(name, pl)| {
+                let ty = self.env.lookup_binding(pl).unwrap_or(TypeVar::Any);
+                (name.clone(), (ty, self.possibly_unbound.contains(pl)))
+            })
+            .collect()
+    }
+}
+
+/// Identifiers assigned by a target like `x` or `a, (b, c)`
+fn target_ids<'t>(target: Node<'t>, ids: &mut Vec<Node<'t>>) {
+    match target.kind() {
+        "identifier" => ids.push(target),
+        "pattern_list"
+        | "tuple_pattern"
+        | "list_pattern"
+        | "tuple"
+        | "list"
+        | "parenthesized_expression" => {
+            for child in target.named_children(&mut target.walk()) {
+                target_ids(child, ids);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Identifiers captured by the pattern of a `case` like `[x, *rest]` or `Point(x=px) as p`
+/// Dotted names like `Color.RED` and the class and keywords of a class pattern are values
+fn pattern_ids<'t>(pattern: Node<'t>, ids: &mut Vec<Node<'t>>) {
+    match pattern.kind() {
+        "dotted_name" if pattern.named_child_count() == 1 => ids.extend(pattern.named_child(0)),
+        "splat_pattern" => ids.extend(pattern.named_child(0)),
+        "as_pattern" => {
+            ids.extend(
+                pattern
+                    .named_children(&mut pattern.walk())
+                    .filter(|c| c.kind() == "identifier"),
+            );
+            if let Some(inner) = pattern.named_child(0) {
+                pattern_ids(inner, ids);
+            }
+        }
+        "class_pattern" | "keyword_pattern" => {
+            for child in pattern.named_children(&mut pattern.walk()).skip(1) {
+                pattern_ids(child, ids);
+            }
+        }
+        "dict_pattern" => {
+            for child in pattern.children_by_field_name("value", &mut pattern.walk()) {
+                pattern_ids(child, ids);
+            }
+            for child in pattern.named_children(&mut pattern.walk()) {
+                if child.kind() == "splat_pattern" {
+                    pattern_ids(child, ids);
+                }
+            }
+        }
+        "case_clause" | "case_pattern" | "list_pattern" | "tuple_pattern" | "union_pattern" => {
+            for child in pattern.named_children(&mut pattern.walk()) {
+                if child.kind() != "block" && child.kind() != "if_clause" {
+                    pattern_ids(child, ids);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Target of `as` in a `with` item like `open(p) as f` or an `except` clause
+fn as_target<'t>(as_pattern: &Node<'t>) -> Option<Node<'t>> {
+    as_pattern
+        .child_by_field_name("alias")
+        .filter(|_| as_pattern.kind() == "as_pattern")
+        .and_then(|alias| alias.named_child(0))
+}
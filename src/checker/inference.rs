@@ -0,0 +1,104 @@
+use crate::{
+    checker::{CheckErr, Checker},
+    type_var::{Param, Place, TypeVar},
+};
+use log::debug;
+use std::collections::HashMap;
+use tree_sitter::Node;
+
+/// A use of a type variable, `actual` is used where `expected` is needed
+pub struct Constraint {
+    expected: TypeVar,
+    actual: TypeVar,
+    start: Place,
+    end: Place,
+}
+
+impl<'a> Checker<'a> {
+    /// Enable inferring the types of unannotated parameters from how they are used
+    pub fn with_param_inference(mut self, enabled: bool) -> Self {
+        self.infer_params = enabled;
+        self
+    }
+
+    /// Record a use of a parameter being inferred, uses without type variables are ignored
+    pub fn constrain(&mut self, expected: &TypeVar, actual: &TypeVar, node: &Node) {
+        if !self.infer_params || !(expected.contains_var() || actual.contains_var()) {
+            return;
+        }
+        self.constraints.push(Constraint {
+            expected: expected.clone(),
+            actual: actual.clone(),
+            start: Place::from_ts_point("start", node.start_position()),
+            end: Place::from_ts_point("end", node.end_position()),
+        });
+    }
+
+    /// Unify the constraints recorded since `start` that involve the parameters `vars`
+    /// Constraints only on variables of an enclosing function are left for it to solve
+    pub fn solve_constraints(&mut self, start: usize, vars: &[Place]) -> HashMap<Place, TypeVar> {
+        let mut subst = HashMap::new();
+        let recorded: Vec<Constraint> = self.constraints.drain(start..).collect();
+        for c in recorded {
+            let own_var = [&c.expected, &c.actual]
+                .into_iter()
+                .flat_map(|ty| ty.members())
+                .find_map(|ty| match ty {
+                    TypeVar::Var(pl) if vars.contains(&pl) => Some(pl),
+                    _ => None,
+                });
+            let Some(var) = own_var else {
+                self.constraints.push(c);
+                continue;
+            };
+            if !TypeVar::unify(&mut subst, &c.expected, &c.actual) {
+                self.report(CheckErr::new(
+                    &format!(
+                        "Conflicting uses of parameter '{}', expected {} found {}",
+                        var.name,
+                        c.expected.substitute(&subst),
+                        c.actual.substitute(&subst)
+                    ),
+                    c.start,
+                    Some(c.end),
+                ));
+            }
+        }
+        debug!("solved {:?}", subst);
+        subst
+    }
+
+    /// Signature of a function with the solutions for its parameters substituted
+    /// Parameters that are still variables after solving are generic
+    pub fn solve_signature(
+        &mut self,
+        start: usize,
+        vars: &[Place],
+        params: Vec<Param>,
+        ret: Vec<TypeVar>,
+    ) -> (Vec<Param>, Vec<TypeVar>) {
+        if !self.infer_params {
+            return (params, ret);
+        }
+        let subst = self.solve_constraints(start, vars);
+        let params = params
+            .into_iter()
+            .map(|p| Param {
+                ty: p.ty.substitute(&subst),
+                ..p
+            })
+            .collect();
+        let ret = ret.iter().map(|t| t.substitute(&subst)).collect();
+        (params, ret)
+    }
+
+    /// Result of calling a function with type variables in its signature
+    /// The variables are bound by the arguments passed to each parameter
+    pub fn instantiate(ret: &TypeVar, bindings: &[(TypeVar, TypeVar)]) -> TypeVar {
+        let mut subst = HashMap::new();
+        for (param, arg) in bindings {
+            TypeVar::unify(&mut subst, param, arg);
+        }
+        ret.substitute(&subst)
+    }
+}
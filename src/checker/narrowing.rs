@@ -0,0 +1,142 @@
+use crate::{
+    checker::Checker,
+    type_var::{Place, TypeVar},
+};
+use log::debug;
+use tree_sitter::{Node, Point};
+
+/// Refined types for variables that hold while a condition is known to be true or false
+/// Later entries for the same variable replace earlier ones
+pub type Narrowing = Vec<(String, TypeVar)>;
+
+impl<'a> Checker<'a> {
+    /// Work out how variables are narrowed when `cond` evaluates to `positive`
+    pub fn narrow_condition(&mut self, cond: &Node, positive: bool) -> Narrowing {
+        match cond.kind() {
+            "parenthesized_expression" => cond
+                .named_child(0)
+                .map(|inner| self.narrow_condition(&inner, positive))
+                .unwrap_or_default(),
+            "not_operator" => cond
+                .child_by_field_name("argument")
+                .map(|arg| self.narrow_condition(&arg, !positive))
+                .unwrap_or_default(),
+            "boolean_operator" => {
+                let op = cond
+                    .child_by_field_name("operator")
+                    .and_then(|op| self.node_text(&op).ok());
+                match (
+                    op,
+                    cond.child_by_field_name("left"),
+                    cond.child_by_field_name("right"),
+                ) {
+                    // both sides are known when `and` is true or `or` is false
+                    (Some("and"), Some(l), Some(r)) if positive => {
+                        let mut narrowing = self.narrow_condition(&l, positive);
+                        narrowing.extend(self.narrow_condition(&r, positive));
+                        narrowing
+                    }
+                    (Some("or"), Some(l), Some(r)) if !positive => {
+                        let mut narrowing = self.narrow_condition(&l, positive);
+                        narrowing.extend(self.narrow_condition(&r, positive));
+                        narrowing
+                    }
+                    _ => vec![],
+                }
+            }
+            "comparison_operator" => self.narrow_none_check(cond, positive).unwrap_or_default(),
+            "call" => self.narrow_isinstance(cond, positive).unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    /// Narrowing for `x is None` and `x is not None`
+    fn narrow_none_check(&mut self, cond: &Node, positive: bool) -> Option<Narrowing> {
+        let operators: Vec<&str> = cond
+            .children_by_field_name("operators", &mut cond.walk())
+            .map(|op| op.kind())
+            .collect();
+        let is_none = match operators.as_slice() {
+            ["is"] => positive,
+            ["is not"] => !positive,
+            _ => return None,
+        };
+        let operands: Vec<Node> = cond.named_children(&mut cond.walk()).collect();
+        let var_node = match operands.as_slice() {
+            [var, none] | [none, var] if none.kind() == "none" && var.kind() == "identifier" => {
+                *var
+            }
+            _ => return None,
+        };
+        let var = self.node_text(&var_node).ok()?;
+        let ty = self.env.var_type(var)?;
+        let narrowed = if is_none {
+            TypeVar::None
+        } else {
+            ty.filter_members(|t| *t != TypeVar::None)
+        };
+        debug!("narrowed {} from {} to {}", var, ty, narrowed);
+        Some(vec![(var.to_owned(), narrowed)])
+    }
+
+    /// Narrowing for `isinstance(x, int)` and `isinstance(x, (int, str))`
+    /// The true branch keeps the members of x that match, the false branch the rest
+    fn narrow_isinstance(&mut self, cond: &Node, positive: bool) -> Option<Narrowing> {
+        let fn_node = cond.child_by_field_name("function")?;
+        if fn_node.kind() != "identifier" || self.node_text(&fn_node).ok()? != "isinstance" {
+            return None;
+        }
+        let args_node = cond.child_by_field_name("arguments")?;
+        let args: Vec<Node> = args_node
+            .named_children(&mut args_node.walk())
+            .filter(|n| n.kind() != "comment")
+            .collect();
+        let [var_node, class_node] = args.as_slice() else {
+            return None;
+        };
+        if var_node.kind() != "identifier" {
+            return None;
+        }
+        let class_nodes: Vec<Node> = match class_node.kind() {
+            "tuple" => class_node.named_children(&mut class_node.walk()).collect(),
+            _ => vec![*class_node],
+        };
+        let classes: Vec<TypeVar> = class_nodes
+            .iter()
+            .map(|n| self.annotation_type(n))
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        let var = self.node_text(var_node).ok()?;
+        let ty = self.env.var_type(var)?;
+        let narrowed = if positive {
+            let mut matching = Vec::new();
+            for member in ty.members() {
+                if classes.iter().any(|cls| member.is_instance_of(cls)) {
+                    matching.push(member);
+                } else {
+                    // a wider type narrows down to the classes checked for
+                    // eg. a float could hold an int
+                    matching.extend(classes.iter().filter(|cls| member.type_check(cls)).cloned());
+                }
+            }
+            TypeVar::union(matching)
+        } else {
+            ty.filter_members(|member| !classes.iter().any(|cls| member.is_instance_of(cls)))
+        };
+        debug!("narrowed {} from {} to {}", var, ty, narrowed);
+        Some(vec![(var.to_owned(), narrowed)])
+    }
+
+    /// Bind each narrowed variable to a new place in the current scope
+    /// A var of an enclosing scope only shadows its place there until control flow joins,
+    /// it isn't a local so it can't become possibly unbound
+    pub fn apply_narrowing(&mut self, narrowing: &Narrowing, at: Point) {
+        for (var, ty) in narrowing {
+            // named apart from the var so it can't collide with an assignment at the same point
+            let narrowed = Place::from_ts_point(&format!("narrowed {}", var), at);
+            self.env.insert_binding(narrowed.clone(), ty.clone());
+            self.env.insert_var(var, narrowed);
+        }
+    }
+}
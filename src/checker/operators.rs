@@ -0,0 +1,384 @@
+use crate::{
+    checker::{CallSig, CheckErr, Checker},
+    type_var::{ClassType, LiteralValue, TypeVar},
+};
+use log::debug;
+use tree_sitter::Node;
+
+/// Builtin classes that describe the primitive and container types
+const PRIMITIVE_CLASSES: &[&str] = &[
+    "int", "float", "complex", "bool", "str", "bytes", "list", "dict", "set", "tuple",
+];
+
+/// Methods implementing a binary operator and its reflected version
+fn binop_dunders(op: &str) -> Option<(&'static str, &'static str)> {
+    let dunders = match op {
+        "+" => ("__add__", "__radd__"),
+        "-" => ("__sub__", "__rsub__"),
+        "*" => ("__mul__", "__rmul__"),
+        "/" => ("__truediv__", "__rtruediv__"),
+        "//" => ("__floordiv__", "__rfloordiv__"),
+        "%" => ("__mod__", "__rmod__"),
+        "**" => ("__pow__", "__rpow__"),
+        "@" => ("__matmul__", "__rmatmul__"),
+        "&" => ("__and__", "__rand__"),
+        "|" => ("__or__", "__ror__"),
+        "^" => ("__xor__", "__rxor__"),
+        "<<" => ("__lshift__", "__rlshift__"),
+        ">>" => ("__rshift__", "__rrshift__"),
+        _ => return None,
+    };
+    Some(dunders)
+}
+
+/// Methods implementing a rich comparison and the one used when the operands are swapped
+fn comparison_dunders(op: &str) -> Option<(&'static str, &'static str)> {
+    let dunders = match op {
+        "<" => ("__lt__", "__gt__"),
+        "<=" => ("__le__", "__ge__"),
+        ">" => ("__gt__", "__lt__"),
+        ">=" => ("__ge__", "__le__"),
+        _ => return None,
+    };
+    Some(dunders)
+}
+
+/// Operand types that aren't known yet could support any operator
+fn is_unknown(ty: &TypeVar) -> bool {
+    matches!(ty, TypeVar::Any | TypeVar::Var(_))
+}
+
+impl<'a> Checker<'a> {
+    /// Type of instances of `cls`, builtin classes for primitives give the primitive type
+    pub fn instance_type(&self, cls: &ClassType) -> TypeVar {
+        let name = cls.name();
+        if PRIMITIVE_CLASSES.contains(&name) && self.env.is_builtin_scope(&cls.scope_name()) {
+            // variable length tuples can't be described yet
+            return TypeVar::from_type_str(name).unwrap_or(TypeVar::Any);
+        }
+        TypeVar::Instance(cls.clone())
+    }
+
+    /// The builtin class describing a primitive or container type
+    fn builtin_class(&self, ty: &TypeVar) -> Option<ClassType> {
+        let name = match ty {
+            TypeVar::Integer() => "int",
+            TypeVar::Float() => "float",
+            TypeVar::Complex() => "complex",
+            TypeVar::Bool() => "bool",
+            TypeVar::String() => "str",
+            TypeVar::Bytes() => "bytes",
+            TypeVar::List(_) => "list",
+            TypeVar::Dict(..) => "dict",
+            TypeVar::Set(_) => "set",
+            TypeVar::Tuple(_) => "tuple",
+            _ => return None,
+        };
+        match self.env.builtin_var_type(name) {
+            Some(TypeVar::Class(cls)) => Some(cls),
+            _ => None,
+        }
+    }
+
+    /// A method looked up on the class of a value, bound to the value
+    pub fn method_of(&self, ty: &TypeVar, name: &str) -> Option<TypeVar> {
+        let cls = match ty {
+            TypeVar::Instance(cls) => cls.clone(),
+            TypeVar::Literal(value) => return self.method_of(&value.base_type(), name),
+            _ => self.builtin_class(ty)?,
+        };
+        self.class_attr(&cls, name)
+            .map(|method| self.bind_method(method, true))
+    }
+
+    /// Result of calling the method `name` of `ty` with a single argument
+    /// `None` when there is no such method or it doesn't accept the argument
+    fn call_dunder(&self, ty: &TypeVar, name: &str, arg: &TypeVar) -> Option<TypeVar> {
+        let method = self.method_of(ty, name)?;
+        match self.call_signature(&method) {
+            CallSig::Known(params, ret) => {
+                let param = params.iter().find(|p| p.is_positional())?;
+                param.ty.type_check(arg).then_some(ret)
+            }
+            CallSig::Unknown(ret) => Some(ret),
+            CallSig::NotCallable => None,
+        }
+    }
+
+    /// Result of calling the method `name` of `ty` without arguments
+    pub fn call_method(&self, ty: &TypeVar, name: &str) -> Option<TypeVar> {
+        let method = self.method_of(ty, name)?;
+        match self.call_signature(&method) {
+            CallSig::Known(_, ret) => Some(ret),
+            CallSig::Unknown(ret) => Some(ret),
+            CallSig::NotCallable => None,
+        }
+    }
+
+    /// Type of the elements from iterating over `ty`, `None` when it isn't iterable
+    /// `ty.__iter__()` gives an iterator and its `__next__()` gives each element
+    pub fn element_type(&self, ty: &TypeVar) -> Option<TypeVar> {
+        if ty.members().len() > 1 {
+            let elements: Option<Vec<TypeVar>> =
+                ty.members().iter().map(|m| self.element_type(m)).collect();
+            return elements.map(TypeVar::union);
+        }
+        if is_unknown(ty) {
+            return Some(TypeVar::Any);
+        }
+        let iterator = self.call_method(ty, "__iter__")?;
+        // the stubs can't describe element types, they are worked out from the container
+        let element = match ty.widened() {
+            TypeVar::List(elem) | TypeVar::Set(elem) | TypeVar::Dict(elem, _) => *elem,
+            TypeVar::Tuple(elems) => TypeVar::union(elems),
+            TypeVar::String() => TypeVar::String(),
+            TypeVar::Bytes() => TypeVar::Integer(),
+            // eg. `__iter__` is a generator or annotated with a type from `typing`
+            _ if is_unknown(&iterator) => TypeVar::Any,
+            _ => self.call_method(&iterator, "__next__")?,
+        };
+        debug!("elements of {} are {}", ty, element);
+        Some(element)
+    }
+
+    /// Type of `left op right`, `None` when the operands don't support the operator
+    /// `left.__op__(right)` is tried first then `right.__rop__(left)`
+    pub fn binop_type(&self, op: &str, left: &TypeVar, right: &TypeVar) -> Option<TypeVar> {
+        let (dunder, reflected) = binop_dunders(op)?;
+        // each combination of union members has to be supported,
+        // an empty union never has a value so there is nothing to combine
+        if left.members().len() != 1 || right.members().len() != 1 {
+            let mut results = Vec::new();
+            for l in left.members() {
+                for r in right.members() {
+                    results.push(self.binop_type(op, &l, &r)?);
+                }
+            }
+            return Some(TypeVar::union(results));
+        }
+        if is_unknown(left) || is_unknown(right) {
+            return Some(TypeVar::Any);
+        }
+        let result = self
+            .call_dunder(left, dunder, right)
+            .or_else(|| self.call_dunder(right, reflected, left))?;
+        debug!("{} {} {} gives {}", left, op, right, result);
+        Some(Self::container_result(op, left, right, result))
+    }
+
+    /// Type of `left op= right`, `None` when the operands don't support the operator
+    /// `left.__iop__(right)` is tried first then the same as `left op right`
+    pub fn augmented_type(&self, op: &str, left: &TypeVar, right: &TypeVar) -> Option<TypeVar> {
+        let (dunder, _) = binop_dunders(op)?;
+        let inplace = format!("__i{}", &dunder[2..]);
+        let single = left.members().len() == 1 && right.members().len() == 1;
+        if single
+            && !is_unknown(left)
+            && !is_unknown(right)
+            && let Some(result) = self.call_dunder(left, &inplace, right)
+        {
+            return Some(Self::container_result(op, left, right, result));
+        }
+        self.binop_type(op, left, right)
+    }
+
+    /// The stubs can't describe element types, they are worked out from the operands here
+    fn container_result(op: &str, left: &TypeVar, right: &TypeVar, result: TypeVar) -> TypeVar {
+        let join = |a: &TypeVar, b: &TypeVar| Box::new(TypeVar::union(vec![a.clone(), b.clone()]));
+        match (op, left, right) {
+            ("+", TypeVar::List(a), TypeVar::List(b)) => TypeVar::List(join(a, b)),
+            ("+", TypeVar::Tuple(a), TypeVar::Tuple(b)) => {
+                TypeVar::Tuple(a.iter().chain(b).cloned().collect())
+            }
+            ("*", TypeVar::List(_), _) => left.clone(),
+            ("*", _, TypeVar::List(_)) => right.clone(),
+            ("|" | "^", TypeVar::Set(a), TypeVar::Set(b)) => TypeVar::Set(join(a, b)),
+            ("&" | "-", TypeVar::Set(_), TypeVar::Set(_)) => left.clone(),
+            ("|", TypeVar::Dict(ak, av), TypeVar::Dict(bk, bv)) => {
+                TypeVar::Dict(join(ak, bk), join(av, bv))
+            }
+            _ => result,
+        }
+    }
+}
+
+impl<'a> Checker<'a> {
+    /// Type of a binary operator expression like `a + b`, or the value an augmented assignment
+    /// like `a += b` assigns
+    pub fn infer_binop(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let left = self.infer_type_for_node(&self.child(node, "left")?)?;
+        let right = self.infer_type_for_node(&self.child(node, "right")?)?;
+        let op_text = self.node_text(&self.child(node, "operator")?)?;
+        let (op, augmented) = match op_text.strip_suffix('=') {
+            Some(op) => (op, true),
+            None => (op_text, false),
+        };
+        // a parameter being inferred is assumed to have the type of the other operand
+        // when the operator works on two values of that type and gives that type back,
+        // otherwise eg. `"ab" * n` or `x / 2` it could be something else and stays unknown
+        let (left, right) = match (&left, &right) {
+            (TypeVar::Var(_), TypeVar::Var(_)) if self.infer_params => {
+                self.constrain(&left, &right, node);
+                return Ok(left);
+            }
+            (var @ TypeVar::Var(_), other) | (other, var @ TypeVar::Var(_))
+                if self.infer_params =>
+            {
+                let other = other.widened();
+                if self.binop_type(op, &other, &other) != Some(other.clone()) {
+                    return Ok(TypeVar::Any);
+                }
+                self.constrain(&other, var, node);
+                (other.clone(), other)
+            }
+            _ => (left, right),
+        };
+        let result = match augmented {
+            true => self.augmented_type(op, &left, &right),
+            false => self.binop_type(op, &left, &right),
+        };
+        result.ok_or_else(|| {
+            CheckErr::new_from_node(
+                &format!(
+                    "Unsupported operand types for {} ({} and {})",
+                    op_text, left, right
+                ),
+                node,
+            )
+        })
+    }
+
+    /// Type of a comparison like `a < b` or a chain like `a < b <= c`
+    /// Each pair of operands is compared, `==`, `!=` and `is` work for any types
+    pub fn infer_comparison(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let operands: Vec<Node> = node
+            .named_children(&mut node.walk())
+            .filter(|n| n.kind() != "comment")
+            .collect();
+        let operators: Vec<Node> = node
+            .children_by_field_name("operators", &mut node.walk())
+            .collect();
+        let operand_types: Vec<TypeVar> = operands
+            .iter()
+            .map(|n| self.infer_type_for_node(n))
+            .collect::<Result<_, _>>()?;
+
+        let mut results = Vec::new();
+        for (i, op_node) in operators.iter().enumerate() {
+            let (Some(left), Some(right)) = (operand_types.get(i), operand_types.get(i + 1)) else {
+                return Err(CheckErr::internal("missing comparison operand", node));
+            };
+            let op = self.node_text(op_node)?;
+            let result = self.comparison_type(op, left, right).ok_or_else(|| {
+                CheckErr::new_from_node(
+                    &format!(
+                        "Unsupported operand types for {} ({} and {})",
+                        op, left, right
+                    ),
+                    node,
+                )
+            })?;
+            results.push(result);
+        }
+        Ok(TypeVar::union(results))
+    }
+
+    /// Result of comparing `left op right`, `None` when the comparison isn't supported
+    fn comparison_type(&self, op: &str, left: &TypeVar, right: &TypeVar) -> Option<TypeVar> {
+        if is_unknown(left) || is_unknown(right) {
+            return Some(TypeVar::Bool());
+        }
+        match op {
+            "==" | "!=" | "<>" | "is" | "is not" => Some(TypeVar::Bool()),
+            // membership falls back to iterating containers without `__contains__`
+            "in" | "not in" => {
+                let members = right.members();
+                members
+                    .iter()
+                    .all(|r| {
+                        is_unknown(r)
+                            || match self.method_of(r, "__contains__") {
+                                Some(_) => self.call_dunder(r, "__contains__", left).is_some(),
+                                None => self.element_type(r).is_some(),
+                            }
+                    })
+                    .then_some(TypeVar::Bool())
+            }
+            _ => {
+                let (dunder, reflected) = comparison_dunders(op)?;
+                let mut results = Vec::new();
+                for l in left.members() {
+                    for r in right.members() {
+                        let result = if is_unknown(&l) || is_unknown(&r) {
+                            TypeVar::Bool()
+                        } else {
+                            self.call_dunder(&l, dunder, &r)
+                                .or_else(|| self.call_dunder(&r, reflected, &l))?
+                        };
+                        results.push(result);
+                    }
+                }
+                Some(TypeVar::union(results))
+            }
+        }
+    }
+
+    /// Type of `-x`, `+x` or `~x` from `__neg__`, `__pos__` or `__invert__`
+    pub fn infer_unary(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let op = self.node_text(&self.child(node, "operator")?)?;
+        let operand = self.infer_type_for_node(&self.child(node, "argument")?)?;
+        // negative constants are literals too
+        if let TypeVar::Literal(LiteralValue::Int(value)) = operand {
+            match op {
+                "-" if value != i128::MIN => {
+                    return Ok(TypeVar::Literal(LiteralValue::Int(-value)));
+                }
+                "+" => return Ok(operand),
+                _ => {}
+            }
+        }
+        let dunder = match op {
+            "-" => "__neg__",
+            "+" => "__pos__",
+            "~" => "__invert__",
+            _ => {
+                return Err(CheckErr::unsupported(
+                    &format!("unsupported operator {}", op),
+                    node,
+                ));
+            }
+        };
+        let mut results = Vec::new();
+        for member in operand.members() {
+            if is_unknown(&member) {
+                results.push(TypeVar::Any);
+                continue;
+            }
+            let result = match self.method_of(&member, dunder) {
+                Some(method) => match self.call_signature(&method) {
+                    CallSig::Known(_, ret) => Some(ret),
+                    CallSig::Unknown(ret) => Some(ret),
+                    CallSig::NotCallable => None,
+                },
+                None => None,
+            };
+            match result {
+                Some(ty) => results.push(ty),
+                None => {
+                    return Err(CheckErr::new_from_node(
+                        &format!("Unsupported operand type for unary {} ({})", op, member),
+                        node,
+                    ));
+                }
+            }
+        }
+        Ok(TypeVar::union(results))
+    }
+
+    /// `a and b` and `a or b` evaluate to one of their operands
+    pub fn infer_boolean_op(&mut self, node: &Node) -> Result<TypeVar, CheckErr> {
+        let left = self.infer_type_for_node(&self.child(node, "left")?)?;
+        let right = self.infer_type_for_node(&self.child(node, "right")?)?;
+        Ok(TypeVar::union(vec![left, right]))
+    }
+}
@@ -1,7 +1,7 @@
 use crate::environment::scope::{Scope, ScopeStack};
 use crate::type_var::{Place, TypeVar};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 mod scope;
@@ -12,6 +12,10 @@ pub struct Environment {
     live_scopes: Rc<RefCell<ScopeStack>>,
     /// hold all scopes that have been used
     scopes: HashMap<String, Rc<RefCell<Scope>>>,
+    /// builtin names, looked up after every live scope
+    builtins: Option<Rc<RefCell<Scope>>>,
+    /// every scope used by the builtins, eg. for builtin classes
+    builtin_scopes: HashMap<String, Rc<RefCell<Scope>>>,
 }
 
 /// Track variables, places and their types
@@ -22,11 +26,53 @@ impl Environment {
         let mut env = Self {
             live_scopes: Rc::new(RefCell::new(ScopeStack::new())),
             scopes,
+            builtins: None,
+            builtin_scopes: HashMap::new(),
         };
         env.create_scope(name);
         env
     }
 
+    /// Use the module scope of `other` as the builtins for this environment
+    pub fn set_builtins(&mut self, other: &Environment) {
+        self.builtins = other.live_scopes.borrow().first().cloned();
+        self.builtin_scopes = other.scopes.clone();
+    }
+
+    fn named_scope(&self, scope_name: &str) -> Option<Rc<RefCell<Scope>>> {
+        self.scopes
+            .get(scope_name)
+            .or_else(|| self.builtin_scopes.get(scope_name))
+            .cloned()
+    }
+
+    /// Whether `scope_name` belongs to the builtins rather than the module being checked
+    pub fn is_builtin_scope(&self, scope_name: &str) -> bool {
+        !self.scopes.contains_key(scope_name) && self.builtin_scopes.contains_key(scope_name)
+    }
+
+    /// Get the type of a var defined directly in the scope `scope_name`, live or not
+    /// Used for looking up attributes in class scopes
+    pub fn scope_var_type(&self, scope_name: &str, var: &str) -> Option<TypeVar> {
+        let scope = self.named_scope(scope_name)?;
+        let scope = scope.borrow();
+        scope.lookup_var(var).and_then(|pl| scope.lookup_place(&pl))
+    }
+
+    /// Place of a var defined directly in the scope `scope_name`, live or not
+    pub fn scope_var(&self, scope_name: &str, var: &str) -> Option<Place> {
+        self.named_scope(scope_name)?.borrow().lookup_var(var)
+    }
+
+    /// Bind a var directly in the scope `scope_name`, live or not
+    pub fn insert_scope_var(&mut self, scope_name: &str, var: &str, pl: Place, ty: TypeVar) {
+        if let Some(scope) = self.named_scope(scope_name) {
+            let mut scope = scope.borrow_mut();
+            scope.insert_binding(pl.clone(), ty);
+            scope.insert_var(var, pl);
+        }
+    }
+
     /// insert into current scope
     pub fn insert_binding(&mut self, pl: Place, ty: TypeVar) {
         if let Some(scope) = self.live_scopes.borrow().last() {
@@ -40,7 +86,9 @@ impl Environment {
                 return Some(ty.clone());
             }
         }
-        None
+        self.builtins
+            .as_ref()
+            .and_then(|scope| scope.borrow().lookup_place(pl))
     }
 
     pub fn insert_var(&mut self, var: &str, pl: Place) {
@@ -49,6 +97,78 @@ impl Environment {
         }
     }
 
+    /// Every var in the innermost scope and the place of its current value
+    pub fn local_vars(&self) -> HashMap<String, Place> {
+        self.live_scopes
+            .borrow()
+            .last()
+            .map(|scope| scope.borrow().vars())
+            .unwrap_or_default()
+    }
+
+    /// Replace the vars of the innermost scope, eg. with the vars where control flow joins
+    pub fn set_local_vars(&mut self, vars: HashMap<String, Place>) {
+        if let Some(scope) = self.live_scopes.borrow().last() {
+            scope.borrow_mut().set_vars(vars);
+        }
+    }
+
+    /// Declare the names assigned somewhere in the innermost scope, like the locals of a function
+    pub fn set_local_names(&mut self, names: HashSet<String>) {
+        if let Some(scope) = self.live_scopes.borrow().last() {
+            scope.borrow_mut().set_locals(names);
+        }
+    }
+
+    /// Place of the var in the innermost scope only
+    pub fn local_var(&self, var: &str) -> Option<Place> {
+        self.live_scopes
+            .borrow()
+            .last()
+            .and_then(|scope| scope.borrow().lookup_var(var))
+    }
+
+    /// Whether `var` is local to the innermost scope but has no value yet
+    /// The module scope falls back to builtins instead
+    pub fn is_unassigned_local(&self, var: &str) -> bool {
+        let scopes = self.live_scopes.borrow();
+        scopes.len() > 1
+            && scopes.last().is_some_and(|scope| {
+                let scope = scope.borrow();
+                scope.is_local(var) && scope.lookup_var(var).is_none()
+            })
+    }
+
+    /// Whether `var` is assigned somewhere in the innermost scope rather than a scope around it
+    pub fn is_local_name(&self, var: &str) -> bool {
+        self.live_scopes
+            .borrow()
+            .last()
+            .is_some_and(|scope| scope.borrow().is_local(var))
+    }
+
+    /// Place of the var in the scopes enclosing the innermost one, or the builtins
+    pub fn enclosing_var(&self, var: &str) -> Option<Place> {
+        for scope in self.live_scopes.borrow().iter().rev().skip(1) {
+            if let Some(pl) = scope.borrow().lookup_var(var) {
+                return Some(pl.clone());
+            }
+        }
+        self.builtins
+            .as_ref()
+            .and_then(|scope| scope.borrow().lookup_var(var))
+    }
+
+    /// Whether a scope enclosing the innermost one assigns `var` somewhere
+    pub fn is_enclosing_local(&self, var: &str) -> bool {
+        let scopes = self.live_scopes.borrow();
+        scopes
+            .iter()
+            .rev()
+            .skip(1)
+            .any(|scope| scope.borrow().is_local(var))
+    }
+
     /// iterate through the live scopes looking for the var
     pub fn lookup_var(&self, var: &str) -> Option<Place> {
         for scope in self.live_scopes.borrow().iter().rev() {
@@ -56,7 +176,15 @@ impl Environment {
                 return Some(pl.clone());
             }
         }
-        None
+        self.builtins
+            .as_ref()
+            .and_then(|scope| scope.borrow().lookup_var(var))
+    }
+
+    /// Type of a builtin name, names in the module that shadow it are ignored
+    pub fn builtin_var_type(&self, var: &str) -> Option<TypeVar> {
+        let scope = self.builtins.as_ref()?.borrow();
+        scope.lookup_var(var).and_then(|pl| scope.lookup_place(&pl))
     }
 
     /// Get the TypeVar for an Identifier like a variable or function name
@@ -183,4 +311,47 @@ mod tests {
 
         assert_eq!(res, ty2)
     }
+
+    #[test]
+    fn scope_vars() {
+        let mut e = Environment::new("module_name");
+        {
+            let _g = e.enter_scope("Foo@1,0");
+        }
+        let pl = Place {
+            name: "x".to_owned(),
+            row: 2,
+            column: 4,
+        };
+        e.insert_scope_var("Foo@1,0", "x", pl, TypeVar::String());
+
+        assert_eq!(e.scope_var_type("Foo@1,0", "x"), Some(TypeVar::String()));
+        assert_eq!(e.var_type("x"), None);
+    }
+
+    #[test]
+    fn builtins_after_module() {
+        let mut builtins = Environment::new("builtins");
+        let pl = Place {
+            name: "len".to_owned(),
+            row: 0,
+            column: 0,
+        };
+        builtins.insert_binding(pl.clone(), TypeVar::Any);
+        builtins.insert_var("len", pl);
+
+        let mut e = Environment::new("module_name");
+        e.set_builtins(&builtins);
+        assert_eq!(e.var_type("len"), Some(TypeVar::Any));
+
+        // module names shadow builtins
+        let pl2 = Place {
+            name: "len".to_owned(),
+            row: 3,
+            column: 0,
+        };
+        e.insert_binding(pl2.clone(), TypeVar::String());
+        e.insert_var("len", pl2);
+        assert_eq!(e.var_type("len"), Some(TypeVar::String()));
+    }
 }
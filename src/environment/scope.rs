@@ -1,5 +1,5 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use crate::type_var::{Place, TypeVar};
@@ -10,6 +10,8 @@ pub struct Scope {
     bindings: HashMap<Place, TypeVar>,
     /// Maps the identifier(as a String) to a place of its current value
     var_place_map: HashMap<String, Place>,
+    /// Names assigned somewhere in the scope, they never refer to an outer scope
+    locals: HashSet<String>,
 }
 
 impl Scope {
@@ -18,6 +20,7 @@ impl Scope {
             name: name.to_owned(),
             bindings: HashMap::new(),
             var_place_map: HashMap::new(),
+            locals: HashSet::new(),
         }
     }
 
@@ -36,6 +39,22 @@ impl Scope {
     pub fn lookup_var(&self, var: &str) -> Option<Place> {
         self.var_place_map.get(var).cloned()
     }
+
+    pub fn vars(&self) -> HashMap<String, Place> {
+        self.var_place_map.clone()
+    }
+
+    pub fn set_vars(&mut self, vars: HashMap<String, Place>) {
+        self.var_place_map = vars;
+    }
+
+    pub fn set_locals(&mut self, locals: HashSet<String>) {
+        self.locals = locals;
+    }
+
+    pub fn is_local(&self, var: &str) -> bool {
+        self.locals.contains(var)
+    }
 }
 
 impl std::fmt::Display for Scope {
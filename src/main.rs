@@ -3,6 +3,7 @@ use std::fs;
 
 mod arg;
 mod ast;
+mod cfg;
 mod checker;
 mod environment;
 mod pretty_printer;
@@ -36,5 +37,7 @@ fn main() {
     if args.get_flag("pretty-print") {
         PrettyPrinter::new(&source_code).print_module(&mut tree.walk());
     }
-    Checker::new(&source_code, file_name).check_module(&mut tree.walk());
+    Checker::new(&source_code, file_name)
+        .with_param_inference(args.get_flag("infer-params"))
+        .check_module(&mut tree.walk());
 }
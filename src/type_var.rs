@@ -1,4 +1,5 @@
-use log::error;
+use log::debug;
+use std::collections::HashMap;
 use tree_sitter::Point;
 
 #[derive(Debug, Eq, Hash, PartialEq, Clone)]
@@ -31,68 +32,491 @@ impl std::fmt::Display for Place {
     }
 }
 
+/// A class defined with a `class` statement
+#[derive(Debug, Eq, Hash, PartialEq, Clone)]
+pub struct ClassType {
+    /// Where the class is defined, the place name is the class name
+    pub place: Place,
+    /// Method resolution order, the class itself followed by its bases in lookup order
+    pub mro: Vec<Place>,
+    /// A base class couldn't be resolved, the class could have any attribute
+    pub opaque_base: bool,
+}
+
+impl ClassType {
+    /// Create a class inheriting from `bases`, the MRO is found with C3 linearization
+    /// Returns `None` when the bases can't be put in a consistent order
+    pub fn with_bases(place: Place, bases: &[ClassType], opaque_base: bool) -> Option<Self> {
+        // merge the MRO of each base with the list of bases itself
+        let mut sequences: Vec<Vec<Place>> = bases.iter().map(|b| b.mro.clone()).collect();
+        sequences.push(bases.iter().map(|b| b.place.clone()).collect());
+
+        let mut mro = vec![place.clone()];
+        loop {
+            sequences.retain(|seq| !seq.is_empty());
+            if sequences.is_empty() {
+                break;
+            }
+            // the next class is the first head that isn't in the tail of any sequence
+            let next = sequences
+                .iter()
+                .map(|seq| &seq[0])
+                .find(|head| !sequences.iter().any(|seq| seq[1..].contains(head)))?
+                .clone();
+            for seq in sequences.iter_mut() {
+                if seq[0] == next {
+                    seq.remove(0);
+                }
+            }
+            mro.push(next);
+        }
+        Some(ClassType {
+            place,
+            mro,
+            opaque_base: opaque_base || bases.iter().any(|b| b.opaque_base),
+        })
+    }
+
+    /// Nominal subtyping, a class is a subclass of itself and everything in its MRO
+    pub fn is_subclass_of(&self, other: &ClassType) -> bool {
+        self.opaque_base || self.mro.contains(&other.place)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.place.name
+    }
+
+    /// Name of the Environment scope holding the class attributes
+    pub fn scope_name(&self) -> String {
+        self.place.to_string()
+    }
+}
+
+/// How arguments are matched to a parameter
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+pub enum ParamKind {
+    /// Before `/`, only passed by position
+    PositionalOnly,
+    /// Passed by position or keyword
+    Normal,
+    /// After `*` or `*args`, only passed by keyword
+    KeywordOnly,
+    /// `*args`, takes any extra positional arguments
+    VarPositional,
+    /// `**kwargs`, takes any extra keyword arguments
+    VarKeyword,
+}
+
+/// A parameter in a function signature
+#[derive(Debug, Eq, Hash, PartialEq, Clone)]
+pub struct Param {
+    pub name: String,
+    pub kind: ParamKind,
+    pub has_default: bool,
+    /// Type of each argument, for `*args` and `**kwargs` this is the type of each extra argument
+    pub ty: TypeVar,
+}
+
+impl Param {
+    /// Whether the parameter can be given by position
+    pub fn is_positional(&self) -> bool {
+        matches!(self.kind, ParamKind::PositionalOnly | ParamKind::Normal)
+    }
+
+    /// Whether the parameter can be given by keyword
+    pub fn is_keyword(&self) -> bool {
+        matches!(self.kind, ParamKind::Normal | ParamKind::KeywordOnly)
+    }
+
+    /// Whether a call has to give an argument for the parameter
+    pub fn is_required(&self) -> bool {
+        !self.has_default && (self.is_positional() || self.is_keyword())
+    }
+}
+
+impl std::fmt::Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let prefix = match self.kind {
+            ParamKind::VarPositional => "*",
+            ParamKind::VarKeyword => "**",
+            _ => "",
+        };
+        write!(f, "{}{}: {}", prefix, self.name, self.ty)?;
+        if self.has_default {
+            write!(f, " = ...")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Eq, Hash, PartialEq, Clone)]
 pub enum TypeVar {
     Any,
-    Integer(usize),
+    Integer(),
+    Float(),
+    Bool(),
+    Complex(),
     String(),
-    Call(Place, Vec<TypeVar>, Vec<TypeVar>),
-    BinOp(Place),
+    Bytes(),
+    List(Box<TypeVar>),
+    Dict(Box<TypeVar>, Box<TypeVar>),
+    Set(Box<TypeVar>),
+    /// Fixed length tuple, one type per element
+    Tuple(Vec<TypeVar>),
     None,
-    Function(Place, Vec<TypeVar>, Vec<TypeVar>),
+    Function(Place, Vec<Param>, Vec<TypeVar>),
     Union(Vec<TypeVar>),
-    Var(Place), // placeholder for unknown
+    /// The class object itself, eg. `Foo`
+    Class(ClassType),
+    /// An instance of a class, eg. the value of `Foo()`
+    Instance(ClassType),
+    /// Type that isn't known yet, parameters being inferred use their place as the variable
+    Var(Place),
+    /// A single constant value, eg. `Literal[1]`
+    Literal(LiteralValue),
+}
+
+/// Value of a `Literal[...]` type
+#[derive(Debug, Eq, Hash, PartialEq, Clone)]
+pub enum LiteralValue {
+    Int(i128),
+    Str(String),
+    Bool(bool),
+    /// Member of an enum class, eg. `Color.RED`
+    Enum(ClassType, String),
+}
+
+impl LiteralValue {
+    /// Type every value of this kind has, eg. `int` for `Literal[1]`
+    pub fn base_type(&self) -> TypeVar {
+        match self {
+            LiteralValue::Int(_) => TypeVar::Integer(),
+            LiteralValue::Str(_) => TypeVar::String(),
+            LiteralValue::Bool(_) => TypeVar::Bool(),
+            LiteralValue::Enum(cls, _) => TypeVar::Instance(cls.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LiteralValue::Int(i) => write!(f, "{}", i),
+            LiteralValue::Str(s) => write!(f, "'{}'", s),
+            LiteralValue::Bool(true) => write!(f, "True"),
+            LiteralValue::Bool(false) => write!(f, "False"),
+            LiteralValue::Enum(cls, member) => write!(f, "{}.{}", cls.name(), member),
+        }
+    }
 }
 
 impl TypeVar {
     /// Check if types are allowed
+    /// `self` is the expected type and `other` is the type being assigned to it
     /// Returns True when the conditions are OK
     /// eg. Int and Any would return `true`
     pub fn type_check(&self, other: &TypeVar) -> bool {
         match (self, other) {
-            (TypeVar::Any, _) | (_, TypeVar::Any) => true,
-            (TypeVar::Union(_left_tys), TypeVar::Union(_right_tys)) => todo!(),
-            (TypeVar::Union(tys), x) | (x, TypeVar::Union(tys)) => tys.contains(x),
+            // type variables aren't known until they are solved
+            (TypeVar::Any | TypeVar::Var(_), _) | (_, TypeVar::Any | TypeVar::Var(_)) => true,
+            // every member of the assigned union has to fit the expected type
+            (_, TypeVar::Union(tys)) => tys.iter().all(|t| self.type_check(t)),
+            // a single type only has to fit one member of the expected union
+            (TypeVar::Union(tys), x) => tys.iter().any(|t| t.type_check(x)),
+            // only the same constant fits a literal type
+            (TypeVar::Literal(l), TypeVar::Literal(r)) => l == r,
+            (TypeVar::Literal(_), _) => false,
+            (l, TypeVar::Literal(r)) => l.type_check(&r.base_type()),
+            // mutable containers are invariant in their element types
+            (TypeVar::List(l), TypeVar::List(r)) | (TypeVar::Set(l), TypeVar::Set(r)) => {
+                l.is_equivalent(r)
+            }
+            (TypeVar::Dict(lk, lv), TypeVar::Dict(rk, rv)) => {
+                lk.is_equivalent(rk) && lv.is_equivalent(rv)
+            }
+            // tuples are immutable so each element only has to fit
+            (TypeVar::Tuple(l), TypeVar::Tuple(r)) => {
+                l.len() == r.len() && l.iter().zip(r).all(|(l, r)| l.type_check(r))
+            }
+            // classes are nominal, a subclass can be used where a base class is expected
+            (TypeVar::Class(l), TypeVar::Class(r))
+            | (TypeVar::Instance(l), TypeVar::Instance(r)) => r.is_subclass_of(l),
+            // numeric tower, eg. an int can be used where a float is expected
+            (l, r) if l.numeric_rank().is_some() && r.numeric_rank().is_some() => {
+                r.numeric_rank() <= l.numeric_rank()
+            }
+            (l, r) => std::mem::discriminant(l) == std::mem::discriminant(r),
+        }
+    }
+
+    /// Position of numeric types in the numeric tower, `bool < int < float < complex`
+    /// `None` for non numeric types
+    pub fn numeric_rank(&self) -> Option<u8> {
+        match self {
+            TypeVar::Bool() => Some(0),
+            TypeVar::Integer() => Some(1),
+            TypeVar::Float() => Some(2),
+            TypeVar::Complex() => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Forget constant values, eg. `Literal[1]` becomes `int`
+    /// Used where a variable could later hold a different value of the same type
+    pub fn widened(&self) -> TypeVar {
+        match self {
+            TypeVar::Literal(value) => value.base_type(),
+            TypeVar::Union(tys) => TypeVar::union(tys.iter().map(|t| t.widened()).collect()),
+            TypeVar::Tuple(tys) => TypeVar::Tuple(tys.iter().map(|t| t.widened()).collect()),
+            ty => ty.clone(),
+        }
+    }
+
+    /// Each type a value could be, the members of a union or just the type itself
+    pub fn members(&self) -> Vec<TypeVar> {
+        match self {
+            TypeVar::Union(tys) => tys.clone(),
+            ty => vec![ty.clone()],
+        }
+    }
+
+    /// Keep only the members matching `keep`, `Any` is left alone since it could be anything
+    /// Removing every member leaves an empty union, a value that can't exist
+    pub fn filter_members(&self, keep: impl Fn(&TypeVar) -> bool) -> TypeVar {
+        match self {
+            TypeVar::Any => TypeVar::Any,
+            ty => TypeVar::union(ty.members().into_iter().filter(|t| keep(t)).collect()),
+        }
+    }
+
+    /// Whether a value of this type always passes `isinstance(value, cls)`
+    /// Unlike `type_check` the numeric tower doesn't apply, an int is not an instance of float
+    pub fn is_instance_of(&self, cls: &TypeVar) -> bool {
+        match (self, cls) {
+            (_, TypeVar::Any) => true,
+            // bool is a subclass of int
+            (TypeVar::Bool(), TypeVar::Integer()) => true,
+            (TypeVar::Any, _) => false,
+            (TypeVar::Literal(value), cls) => value.base_type().is_instance_of(cls),
+            (TypeVar::Instance(l), TypeVar::Instance(r)) => l.is_subclass_of(r),
             (l, r) => std::mem::discriminant(l) == std::mem::discriminant(r),
         }
     }
 
+    /// Types are equivalent when each one can be assigned to the other
+    /// eg. `Union(Integer, String)` and `Union(String, Integer)`
+    pub fn is_equivalent(&self, other: &TypeVar) -> bool {
+        match (self, other) {
+            // type variables would fit anything, only the same variable is equivalent
+            (TypeVar::Var(_), _) | (_, TypeVar::Var(_)) => self == other,
+            _ => self.type_check(other) && other.type_check(self),
+        }
+    }
+
+    /// Whether a type variable appears anywhere in the type
+    pub fn contains_var(&self) -> bool {
+        match self {
+            TypeVar::Var(_) => true,
+            TypeVar::List(t) | TypeVar::Set(t) => t.contains_var(),
+            TypeVar::Dict(k, v) => k.contains_var() || v.contains_var(),
+            TypeVar::Tuple(tys) | TypeVar::Union(tys) => tys.iter().any(|t| t.contains_var()),
+            TypeVar::Function(_, params, ret) => {
+                params.iter().any(|p| p.ty.contains_var()) || ret.iter().any(|t| t.contains_var())
+            }
+            _ => false,
+        }
+    }
+
+    /// Replace the type variables bound in `subst`, variables bound to other variables are followed
+    pub fn substitute(&self, subst: &HashMap<Place, TypeVar>) -> TypeVar {
+        let sub = |t: &TypeVar| Box::new(t.substitute(subst));
+        match self {
+            TypeVar::Var(p) => match subst.get(p) {
+                Some(ty) => ty.substitute(subst),
+                None => self.clone(),
+            },
+            TypeVar::List(t) => TypeVar::List(sub(t)),
+            TypeVar::Set(t) => TypeVar::Set(sub(t)),
+            TypeVar::Dict(k, v) => TypeVar::Dict(sub(k), sub(v)),
+            TypeVar::Tuple(tys) => {
+                TypeVar::Tuple(tys.iter().map(|t| t.substitute(subst)).collect())
+            }
+            TypeVar::Union(tys) => {
+                TypeVar::union(tys.iter().map(|t| t.substitute(subst)).collect())
+            }
+            TypeVar::Function(pl, params, ret) => TypeVar::Function(
+                pl.clone(),
+                params
+                    .iter()
+                    .map(|p| Param {
+                        ty: p.ty.substitute(subst),
+                        ..p.clone()
+                    })
+                    .collect(),
+                ret.iter().map(|t| t.substitute(subst)).collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    /// Unify two types, binding type variables in `subst` so both sides fit
+    /// Types that don't contain variables only have to be compatible
+    /// Returns false when the types conflict
+    pub fn unify(subst: &mut HashMap<Place, TypeVar>, a: &TypeVar, b: &TypeVar) -> bool {
+        match (a, b) {
+            (TypeVar::Any, _) | (_, TypeVar::Any) => true,
+            (TypeVar::Var(p), t) | (t, TypeVar::Var(p)) => Self::bind_var(subst, p, t),
+            (TypeVar::List(l), TypeVar::List(r)) | (TypeVar::Set(l), TypeVar::Set(r)) => {
+                Self::unify(subst, l, r)
+            }
+            (TypeVar::Dict(lk, lv), TypeVar::Dict(rk, rv)) => {
+                Self::unify(subst, lk, rk) && Self::unify(subst, lv, rv)
+            }
+            (TypeVar::Tuple(l), TypeVar::Tuple(r)) if l.len() == r.len() => {
+                l.iter().zip(r).all(|(l, r)| Self::unify(subst, l, r))
+            }
+            (l, r) => l.type_check(r) || r.type_check(l),
+        }
+    }
+
+    fn bind_var(subst: &mut HashMap<Place, TypeVar>, var: &Place, ty: &TypeVar) -> bool {
+        // literal values don't matter for the type of a variable
+        let ty = ty.widened().substitute(subst);
+        if ty == TypeVar::Var(var.clone()) {
+            return true;
+        }
+        let Some(bound) = subst.get(var).cloned() else {
+            // occurs check, a variable can't contain itself
+            if ty.mentions(var) {
+                return false;
+            }
+            subst.insert(var.clone(), ty);
+            return true;
+        };
+        if !Self::unify(subst, &bound, &ty) {
+            return false;
+        }
+        // keep the wider type so every use is accepted, eg. float for `x + 1` and `x + 1.5`
+        let bound = bound.substitute(subst);
+        if !bound.type_check(&ty) && ty.type_check(&bound) && !ty.mentions(var) {
+            subst.insert(var.clone(), ty);
+        }
+        true
+    }
+
+    /// Whether the type variable `var` appears in the type
+    fn mentions(&self, var: &Place) -> bool {
+        match self {
+            TypeVar::Var(p) => p == var,
+            TypeVar::List(t) | TypeVar::Set(t) => t.mentions(var),
+            TypeVar::Dict(k, v) => k.mentions(var) || v.mentions(var),
+            TypeVar::Tuple(tys) | TypeVar::Union(tys) => tys.iter().any(|t| t.mentions(var)),
+            TypeVar::Function(_, params, ret) => {
+                params.iter().any(|p| p.ty.mentions(var)) || ret.iter().any(|t| t.mentions(var))
+            }
+            _ => false,
+        }
+    }
+
+    /// Build a normalized union
+    /// Nested unions are flattened, duplicates removed and `Any` absorbs everything else.
+    /// A union of a single type is just that type
+    pub fn union(tys: Vec<TypeVar>) -> TypeVar {
+        let mut members: Vec<TypeVar> = Vec::new();
+        for ty in tys {
+            let flat = match ty {
+                TypeVar::Union(inner) => match TypeVar::union(inner) {
+                    TypeVar::Union(v) => v,
+                    single => vec![single],
+                },
+                single => vec![single],
+            };
+            for t in flat {
+                if t == TypeVar::Any {
+                    return TypeVar::Any;
+                }
+                if !members.iter().any(|m| m.is_equivalent(&t)) {
+                    members.push(t);
+                }
+            }
+        }
+        if members.len() == 1 {
+            members.pop().unwrap_or(TypeVar::Any)
+        } else {
+            TypeVar::Union(members)
+        }
+    }
+
+    /// Type for a plain name used in an annotation like `int` or `List`
     pub fn from_type_str(ty_str: &str) -> Option<Self> {
         match ty_str {
-            "int" => Some(Self::Integer(0)), // default 0, this value probabaly doesnt matter?
+            "int" => Some(Self::Integer()),
+            "float" => Some(Self::Float()),
+            "bool" => Some(Self::Bool()),
+            "complex" => Some(Self::Complex()),
             "str" => Some(Self::String()),
+            "bytes" => Some(Self::Bytes()),
+            "None" => Some(Self::None),
+            // every value is an object, there is nothing narrower to check against yet
+            "Any" | "object" => Some(Self::Any),
+            // containers without parameters hold anything
+            "list" | "List" => Some(Self::List(Box::new(Self::Any))),
+            "set" | "Set" => Some(Self::Set(Box::new(Self::Any))),
+            "dict" | "Dict" => Some(Self::Dict(Box::new(Self::Any), Box::new(Self::Any))),
             _ => {
-                error!("{} not able to be converted to type", ty_str);
+                debug!("{} not able to be converted to type", ty_str);
                 None
             }
         }
     }
+
+    /// Type for a subscripted annotation like `list[int]` or `Optional[str]`
+    /// `name` is the subscripted name without any `typing.` prefix
+    pub fn from_generic(name: &str, params: &[TypeVar]) -> Option<Self> {
+        match (name, params) {
+            ("list" | "List", [elem]) => Some(Self::List(Box::new(elem.clone()))),
+            ("set" | "Set", [elem]) => Some(Self::Set(Box::new(elem.clone()))),
+            ("dict" | "Dict", [k, v]) => Some(Self::Dict(Box::new(k.clone()), Box::new(v.clone()))),
+            ("tuple" | "Tuple", elems) => Some(Self::Tuple(elems.to_vec())),
+            ("Optional", [ty]) => Some(Self::union(vec![ty.clone(), Self::None])),
+            ("Union", tys) if !tys.is_empty() => Some(Self::union(tys.to_vec())),
+            _ => {
+                debug!("{}[{:?}] not able to be converted to type", name, params);
+                None
+            }
+        }
+    }
+
+    /// Whether `name` is a type that takes subscript parameters
+    pub fn is_generic_name(name: &str) -> bool {
+        matches!(
+            name,
+            "list"
+                | "List"
+                | "set"
+                | "Set"
+                | "dict"
+                | "Dict"
+                | "tuple"
+                | "Tuple"
+                | "Optional"
+                | "Union"
+        )
+    }
 }
 
 impl std::fmt::Display for TypeVar {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Self::Any => write!(f, "Any()"),
-            Self::Integer(i) => write!(f, "Integer({})", i),
-            Self::Call(p, param, ret) => {
-                let params_str = param
-                    .iter()
-                    .map(|x| format!("{}", x))
-                    .collect::<Vec<String>>()
-                    .join(",");
-                let return_str = ret
-                    .iter()
-                    .map(|x| format!("{}", x))
-                    .collect::<Vec<String>>()
-                    .join(", ");
-                write!(f, "Call({}, [{}] -> [{}])", p, params_str, return_str)
-            }
+            Self::Integer() => write!(f, "Integer()"),
+            Self::Literal(value) => write!(f, "Literal[{}]", value),
             Self::Function(p, param, ret) => {
                 let params_str = param
                     .iter()
                     .map(|x| format!("{}", x))
                     .collect::<Vec<String>>()
-                    .join(",");
+                    .join(", ");
                 let return_str = ret
                     .iter()
                     .map(|x| format!("{}", x))
@@ -108,10 +532,177 @@ impl std::fmt::Display for TypeVar {
                     .join(", ");
                 write!(f, "Union({})", vals)
             }
-            Self::BinOp(p) => write!(f, "BinOp({})", p),
+            Self::Class(c) => write!(f, "Class({})", c.name()),
+            Self::Instance(c) => write!(f, "Instance({})", c.name()),
             Self::Var(p) => write!(f, "Var({})", p),
+            Self::Float() => write!(f, "Float()"),
+            Self::Bool() => write!(f, "Bool()"),
+            Self::Complex() => write!(f, "Complex()"),
             Self::String() => write!(f, "String()"),
+            Self::Bytes() => write!(f, "Bytes()"),
+            Self::List(t) => write!(f, "List({})", t),
+            Self::Dict(k, v) => write!(f, "Dict({}, {})", k, v),
+            Self::Set(t) => write!(f, "Set({})", t),
+            Self::Tuple(v) => {
+                let vals = v
+                    .iter()
+                    .map(|x| format!("{}", x))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "Tuple({})", vals)
+            }
             Self::None => write!(f, "None"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn union_order_does_not_matter() {
+        let a = TypeVar::Union(vec![TypeVar::Integer(), TypeVar::String()]);
+        let b = TypeVar::Union(vec![TypeVar::String(), TypeVar::Integer()]);
+
+        assert!(a.type_check(&b));
+        assert!(b.type_check(&a));
+        assert!(a.is_equivalent(&b));
+    }
+
+    #[test]
+    fn union_subset() {
+        let wide = TypeVar::Union(vec![TypeVar::Integer(), TypeVar::String(), TypeVar::None]);
+        let narrow = TypeVar::Union(vec![TypeVar::None, TypeVar::Integer()]);
+
+        assert!(wide.type_check(&narrow));
+        assert!(!narrow.type_check(&wide));
+        assert!(!TypeVar::Integer().type_check(&narrow));
+    }
+
+    #[test]
+    fn numeric_tower() {
+        assert!(TypeVar::Float().type_check(&TypeVar::Integer()));
+        assert!(TypeVar::Integer().type_check(&TypeVar::Bool()));
+        assert!(TypeVar::Complex().type_check(&TypeVar::Bool()));
+        assert!(!TypeVar::Integer().type_check(&TypeVar::Float()));
+        assert!(!TypeVar::Bool().type_check(&TypeVar::Integer()));
+        assert!(!TypeVar::String().type_check(&TypeVar::Bytes()));
+    }
+
+    #[test]
+    fn container_variance() {
+        let list_int = TypeVar::from_generic("list", &[TypeVar::Integer()]).unwrap();
+        let list_bool = TypeVar::from_generic("List", &[TypeVar::Bool()]).unwrap();
+        assert!(!list_int.type_check(&list_bool));
+        assert!(list_int.type_check(&TypeVar::from_type_str("list").unwrap()));
+
+        let tup = TypeVar::from_generic("tuple", &[TypeVar::Integer(), TypeVar::String()]).unwrap();
+        let tup_bool = TypeVar::Tuple(vec![TypeVar::Bool(), TypeVar::String()]);
+        assert!(tup.type_check(&tup_bool));
+        assert!(!tup.type_check(&TypeVar::Tuple(vec![TypeVar::Integer()])));
+
+        assert_eq!(TypeVar::from_generic("dict", &[TypeVar::String()]), None);
+    }
+
+    #[test]
+    fn instance_checks() {
+        assert!(TypeVar::Bool().is_instance_of(&TypeVar::Integer()));
+        assert!(!TypeVar::Integer().is_instance_of(&TypeVar::Float()));
+        assert!(
+            TypeVar::List(Box::new(TypeVar::String()))
+                .is_instance_of(&TypeVar::List(Box::new(TypeVar::Any)))
+        );
+        assert!(!TypeVar::Any.is_instance_of(&TypeVar::String()));
+    }
+
+    fn class(name: &str, bases: &[ClassType]) -> ClassType {
+        let place = Place {
+            name: name.to_owned(),
+            row: 0,
+            column: 0,
+        };
+        ClassType::with_bases(place, bases, false).unwrap()
+    }
+
+    #[test]
+    fn c3_linearization() {
+        let o = class("O", &[]);
+        let a = class("A", std::slice::from_ref(&o));
+        let b = class("B", std::slice::from_ref(&o));
+        let c = class("C", &[a.clone(), b.clone()]);
+        let names: Vec<&str> = c.mro.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["C", "A", "B", "O"]);
+
+        // O before A and A before O can't both hold
+        let place = Place {
+            name: "Bad".to_owned(),
+            row: 0,
+            column: 0,
+        };
+        assert_eq!(
+            ClassType::with_bases(place, &[o.clone(), a.clone()], false),
+            None
+        );
+
+        let sub = TypeVar::Instance(c);
+        assert!(TypeVar::Instance(a.clone()).type_check(&sub));
+        assert!(!sub.type_check(&TypeVar::Instance(a)));
+        assert!(!TypeVar::Instance(b).type_check(&TypeVar::Instance(o)));
+    }
+
+    #[test]
+    fn unify_type_vars() {
+        let var = |name: &str| {
+            TypeVar::Var(Place {
+                name: name.to_owned(),
+                row: 0,
+                column: 0,
+            })
+        };
+        let mut subst = HashMap::new();
+        assert!(TypeVar::unify(&mut subst, &var("a"), &var("b")));
+        assert!(TypeVar::unify(
+            &mut subst,
+            &var("b"),
+            &TypeVar::Literal(LiteralValue::Int(1))
+        ));
+        assert_eq!(var("a").substitute(&subst), TypeVar::Integer());
+        // a wider use widens the variable
+        assert!(TypeVar::unify(&mut subst, &TypeVar::Float(), &var("a")));
+        assert_eq!(var("b").substitute(&subst), TypeVar::Float());
+        assert!(!TypeVar::unify(&mut subst, &TypeVar::String(), &var("a")));
+
+        // a variable can't contain itself
+        let list_of_c = TypeVar::List(Box::new(var("c")));
+        assert!(!TypeVar::unify(&mut subst, &var("c"), &list_of_c));
+        assert!(TypeVar::unify(
+            &mut subst,
+            &TypeVar::List(Box::new(TypeVar::String())),
+            &list_of_c
+        ));
+        assert_eq!(
+            list_of_c.substitute(&subst),
+            TypeVar::List(Box::new(TypeVar::String()))
+        );
+    }
+
+    #[test]
+    fn union_normalize() {
+        let nested = TypeVar::union(vec![
+            TypeVar::Integer(),
+            TypeVar::Union(vec![TypeVar::String(), TypeVar::Integer()]),
+            TypeVar::String(),
+        ]);
+        assert_eq!(
+            nested,
+            TypeVar::Union(vec![TypeVar::Integer(), TypeVar::String()])
+        );
+
+        let with_any = TypeVar::union(vec![TypeVar::String(), TypeVar::Any]);
+        assert_eq!(with_any, TypeVar::Any);
+
+        let single = TypeVar::union(vec![TypeVar::None, TypeVar::None]);
+        assert_eq!(single, TypeVar::None);
+    }
+}